@@ -0,0 +1,329 @@
+use shogi::{Move, Position};
+use std::thread;
+use std::time::{Duration, Instant};
+use usi::{BestMoveParams, EngineCommand, GameOverKind, GuiCommand, ThinkParams};
+
+use crate::error::Error;
+use crate::process::EngineProcess;
+use crate::profile::EngineProfile;
+
+const STARTPOS: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+// Black is checkmated by the gold on 1h supported by the silver on 2g.
+const MATED_SFEN: &str = "4k4/9/9/9/9/9/7s1/8g/8K b - 1";
+// Black has 10 pieces and 28 points in the enemy camp, satisfying the entering king rule.
+const DECLARE_WIN_SFEN: &str = "4K4/RB5BR/1PPPPPP2/9/9/9/9/9/4k4 b 2G 1";
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+const SEARCH_WAIT: Duration = Duration::from_millis(500);
+const BYOYOMI: Duration = Duration::from_millis(1000);
+
+/// Represents the outcome of a conformance check scenario.
+pub struct ScenarioResult {
+    pub name: &'static str,
+    pub outcome: Result<String, String>,
+    pub elapsed: Duration,
+}
+
+type Scenario = fn(&mut EngineProcess) -> Result<String, String>;
+
+const SCENARIOS: [(&str, Scenario); 10] = [
+    ("handshake", check_handshake),
+    ("isready during search", check_isready_during_search),
+    ("stop during go infinite", check_stop_infinite),
+    ("go ponder then ponderhit", check_ponderhit),
+    ("go ponder then stop", check_ponder_stop),
+    ("gameover then usinewgame", check_new_game),
+    ("bestmove resign when mated", check_resign),
+    ("bestmove win when declarable", check_declare_win),
+    ("byoyomi precision", check_byoyomi),
+    ("quit", check_quit),
+];
+
+/// Drives the engine through scripted scenarios to check its conformance to the USI protocol.
+///
+/// Scenarios are run in order against a single engine process, so a failure in a scenario may
+/// cause the following scenarios to fail as well.
+pub fn run_checks(engine_path: &str, working_dir: &str) -> Result<Vec<ScenarioResult>, Error> {
+    let mut process = EngineProcess::spawn(engine_path, working_dir)?;
+
+    Ok(SCENARIOS
+        .iter()
+        .map(|(name, scenario)| {
+            // Discards outputs left by the previous scenario.
+            while let Ok(Some(_)) = process.recv_timeout(Duration::from_millis(0)) {}
+
+            let start = Instant::now();
+            let outcome = scenario(&mut process);
+            ScenarioResult {
+                name,
+                outcome,
+                elapsed: start.elapsed(),
+            }
+        })
+        .collect())
+}
+
+fn check_handshake(process: &mut EngineProcess) -> Result<String, String> {
+    let profile = EngineProfile::handshake(process).map_err(|e| e.to_string())?;
+    if profile.name.is_empty() {
+        return Err("'id name' was not returned before 'usiok'".to_string());
+    }
+
+    send(process, &GuiCommand::UsiNewGame)?;
+    Ok(format!(
+        "{} ({} options)",
+        profile.name,
+        profile.options.len()
+    ))
+}
+
+fn check_isready_during_search(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::Position(STARTPOS.to_string()))?;
+    send(process, &GuiCommand::Go(ThinkParams::new().infinite()))?;
+    thread::sleep(SEARCH_WAIT);
+
+    send(process, &GuiCommand::IsReady)?;
+    let result = expect(process, "readyok", |cmd| *cmd == EngineCommand::ReadyOk);
+
+    send(process, &GuiCommand::Stop)?;
+    expect_bestmove(process)?;
+    result.map(|_| "readyok returned while searching".to_string())
+}
+
+fn check_stop_infinite(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::Position(STARTPOS.to_string()))?;
+    send(process, &GuiCommand::Go(ThinkParams::new().infinite()))?;
+    expect_no_bestmove(process, SEARCH_WAIT, "go infinite")?;
+
+    send(process, &GuiCommand::Stop)?;
+    let best_move = expect_bestmove(process)?;
+    verify_move(STARTPOS, &[], &best_move)
+}
+
+fn check_ponderhit(process: &mut EngineProcess) -> Result<String, String> {
+    send(
+        process,
+        &GuiCommand::Position(format!("{STARTPOS} moves 7g7f 3c3d")),
+    )?;
+    send(process, &GuiCommand::Go(byoyomi_params().ponder()))?;
+    expect_no_bestmove(process, SEARCH_WAIT, "go ponder")?;
+
+    send(process, &GuiCommand::Ponderhit)?;
+    let start = Instant::now();
+    let best_move = expect_bestmove(process)?;
+    let elapsed = start.elapsed();
+    if elapsed > BYOYOMI {
+        return Err(format!(
+            "bestmove took {}ms after ponderhit, exceeding byoyomi {}ms",
+            elapsed.as_millis(),
+            BYOYOMI.as_millis()
+        ));
+    }
+
+    verify_move(STARTPOS, &["7g7f", "3c3d"], &best_move)
+}
+
+fn check_ponder_stop(process: &mut EngineProcess) -> Result<String, String> {
+    send(
+        process,
+        &GuiCommand::Position(format!("{STARTPOS} moves 7g7f 3c3d")),
+    )?;
+    send(process, &GuiCommand::Go(byoyomi_params().ponder()))?;
+    expect_no_bestmove(process, SEARCH_WAIT, "go ponder")?;
+
+    send(process, &GuiCommand::Stop)?;
+    expect_bestmove(process).map(|m| format!("returned {m} after stop"))
+}
+
+fn check_new_game(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::GameOver(GameOverKind::Lose))?;
+    send(process, &GuiCommand::IsReady)?;
+    expect(process, "readyok", |cmd| *cmd == EngineCommand::ReadyOk)?;
+    send(process, &GuiCommand::UsiNewGame)?;
+
+    send(process, &GuiCommand::Position(STARTPOS.to_string()))?;
+    send(process, &GuiCommand::Go(byoyomi_params()))?;
+    let best_move = expect_bestmove(process)?;
+    verify_move(STARTPOS, &[], &best_move)
+}
+
+fn check_resign(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::Position(MATED_SFEN.to_string()))?;
+    send(process, &GuiCommand::Go(byoyomi_params()))?;
+    match expect_bestmove(process)?.as_str() {
+        "resign" => Ok("resigned".to_string()),
+        m => Err(format!("expected 'bestmove resign', but got '{m}'")),
+    }
+}
+
+fn check_declare_win(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::Position(DECLARE_WIN_SFEN.to_string()))?;
+    send(process, &GuiCommand::Go(byoyomi_params()))?;
+    match expect_bestmove(process)?.as_str() {
+        "win" => Ok("declared winning".to_string()),
+        m => Err(format!("expected 'bestmove win', but got '{m}'")),
+    }
+}
+
+fn check_byoyomi(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::Position(STARTPOS.to_string()))?;
+    send(process, &GuiCommand::Go(byoyomi_params()))?;
+    let start = Instant::now();
+    expect_bestmove(process)?;
+
+    let elapsed = start.elapsed();
+    if elapsed > BYOYOMI {
+        Err(format!(
+            "bestmove took {}ms, exceeding byoyomi {}ms",
+            elapsed.as_millis(),
+            BYOYOMI.as_millis()
+        ))
+    } else {
+        Ok(format!(
+            "bestmove in {}ms of byoyomi {}ms",
+            elapsed.as_millis(),
+            BYOYOMI.as_millis()
+        ))
+    }
+}
+
+fn check_quit(process: &mut EngineProcess) -> Result<String, String> {
+    send(process, &GuiCommand::Quit)?;
+    match process.wait_exit(RESPONSE_TIMEOUT) {
+        Ok(true) => Ok("exited".to_string()),
+        Ok(false) => Err(format!(
+            "the process did not exit within {}s",
+            RESPONSE_TIMEOUT.as_secs()
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn byoyomi_params() -> ThinkParams {
+    ThinkParams::new()
+        .btime(Duration::from_secs(0))
+        .wtime(Duration::from_secs(0))
+        .byoyomi(BYOYOMI)
+}
+
+fn send(process: &mut EngineProcess, cmd: &GuiCommand) -> Result<(), String> {
+    process
+        .send(cmd)
+        .map_err(|e| format!("failed to send '{cmd}': {e}"))
+}
+
+fn expect<P>(process: &EngineProcess, what: &str, pred: P) -> Result<(), String>
+where
+    P: FnMut(&EngineCommand) -> bool,
+{
+    process
+        .wait_for(RESPONSE_TIMEOUT, pred, |_| {})
+        .map(|_| ())
+        .map_err(|e| match e {
+            Error::Timeout => format!(
+                "'{what}' was not returned within {}s",
+                RESPONSE_TIMEOUT.as_secs()
+            ),
+            e => e.to_string(),
+        })
+}
+
+/// Waits for `bestmove` command and returns its move, `resign` or `win`.
+fn expect_bestmove(process: &EngineProcess) -> Result<String, String> {
+    let mut best_move = String::new();
+    expect(process, "bestmove", |cmd| match *cmd {
+        EngineCommand::BestMove(BestMoveParams::MakeMove(ref m, _)) => {
+            best_move = m.to_string();
+            true
+        }
+        EngineCommand::BestMove(BestMoveParams::Resign) => {
+            best_move = "resign".to_string();
+            true
+        }
+        EngineCommand::BestMove(BestMoveParams::Win) => {
+            best_move = "win".to_string();
+            true
+        }
+        _ => false,
+    })?;
+    Ok(best_move)
+}
+
+/// Fails if `bestmove` is returned within `duration`, which is not allowed until `stop` or `ponderhit`.
+fn expect_no_bestmove(
+    process: &EngineProcess,
+    duration: Duration,
+    context: &str,
+) -> Result<(), String> {
+    let deadline = Instant::now() + duration;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match process.recv_timeout(remaining) {
+            Ok(Some(output)) => {
+                if let Some(EngineCommand::BestMove(_)) = output.response() {
+                    return Err(format!(
+                        "'{}' was returned during {context} before 'stop' or 'ponderhit'",
+                        output.raw_str().trim()
+                    ));
+                }
+            }
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+fn verify_move(sfen: &str, moves: &[&str], best_move: &str) -> Result<String, String> {
+    let mut pos = Position::new();
+    pos.set_sfen(sfen).map_err(|e| e.to_string())?;
+    for m in moves {
+        pos.make_move(Move::from_sfen(m).unwrap())
+            .map_err(|e| e.to_string())?;
+    }
+
+    match Move::from_sfen(best_move) {
+        Some(m) => pos
+            .make_move(m)
+            .map(|_| format!("returned {best_move}"))
+            .map_err(|e| format!("illegal move {best_move}: {e}")),
+        None => Err(format!("expected a move, but got '{best_move}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEngine;
+
+    #[test]
+    fn passing_scenarios() {
+        let mut process = MockEngine::new("b").spawn();
+
+        assert_eq!(
+            Ok("b (1 options)".to_string()),
+            check_handshake(&mut process)
+        );
+        assert_eq!(Ok("resigned".to_string()), check_resign(&mut process));
+    }
+
+    #[test]
+    fn failing_scenarios() {
+        // The mock engine resigns immediately, even during "go infinite" or when it can declare.
+        let mut process = MockEngine::new("b").spawn();
+        check_handshake(&mut process).unwrap();
+
+        let result = check_stop_infinite(&mut process);
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|e| e.contains("'bestmove resign' was returned during go infinite")),
+            "{result:?}"
+        );
+
+        while let Ok(Some(_)) = process.recv_timeout(Duration::from_millis(0)) {}
+        assert_eq!(
+            Err("expected 'bestmove win', but got 'resign'".to_string()),
+            check_declare_win(&mut process)
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use shogi::Color;
+use std::fmt;
+use std::time::Duration;
+
+/// Represents the time control of a game along with the remaining time of each player.
+///
+/// Unlike `shogi::TimeControl`, moves-per-period time controls are supported as well.
+#[derive(Debug, Clone, Copy)]
+pub enum TimeControl {
+    Byoyomi {
+        black_time: Duration,
+        white_time: Duration,
+        black_byoyomi: Duration,
+        white_byoyomi: Duration,
+    },
+    FischerClock {
+        black_time: Duration,
+        white_time: Duration,
+        black_inc: Duration,
+        white_inc: Duration,
+    },
+    /// Adds `period_time` to the clock of a player after every `moves` moves of the player.
+    MovesPerPeriod {
+        black_time: Duration,
+        white_time: Duration,
+        moves: u32,
+        period_time: Duration,
+        black_played: u32,
+        white_played: u32,
+    },
+}
+
+impl TimeControl {
+    /// Creates a moves-per-period time control starting with the given time on each clock.
+    pub fn moves_per_period(
+        black_time: Duration,
+        white_time: Duration,
+        moves: u32,
+        period_time: Duration,
+    ) -> TimeControl {
+        TimeControl::MovesPerPeriod {
+            black_time,
+            white_time,
+            moves,
+            period_time,
+            black_played: 0,
+            white_played: 0,
+        }
+    }
+
+    /// Returns the current remaining time for the black player.
+    pub fn black_time(&self) -> Duration {
+        match *self {
+            TimeControl::Byoyomi { black_time, .. }
+            | TimeControl::FischerClock { black_time, .. }
+            | TimeControl::MovesPerPeriod { black_time, .. } => black_time,
+        }
+    }
+
+    /// Returns the current remaining time for the white player.
+    pub fn white_time(&self) -> Duration {
+        match *self {
+            TimeControl::Byoyomi { white_time, .. }
+            | TimeControl::FischerClock { white_time, .. }
+            | TimeControl::MovesPerPeriod { white_time, .. } => white_time,
+        }
+    }
+
+    /// Returns the current remaining time for the given player.
+    pub fn remaining(&self, c: Color) -> Duration {
+        if c == Color::Black {
+            self.black_time()
+        } else {
+            self.white_time()
+        }
+    }
+
+    /// Returns the number of moves the given player has to play until the next period starts.
+    pub fn moves_to_go(&self, c: Color) -> Option<u32> {
+        match *self {
+            TimeControl::MovesPerPeriod {
+                moves,
+                black_played,
+                white_played,
+                ..
+            } => {
+                let played = if c == Color::Black {
+                    black_played
+                } else {
+                    white_played
+                };
+                Some(moves - played)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the time the given player can use for the current move without losing on time.
+    pub fn allotted_time(&self, c: Color) -> Duration {
+        match *self {
+            TimeControl::Byoyomi {
+                black_byoyomi,
+                white_byoyomi,
+                ..
+            } => {
+                let byoyomi = if c == Color::Black {
+                    black_byoyomi
+                } else {
+                    white_byoyomi
+                };
+                self.remaining(c) + byoyomi
+            }
+            // The increment is added before the move is charged.
+            TimeControl::FischerClock {
+                black_inc,
+                white_inc,
+                ..
+            } => {
+                let inc = if c == Color::Black {
+                    black_inc
+                } else {
+                    white_inc
+                };
+                self.remaining(c) + inc
+            }
+            TimeControl::MovesPerPeriod { .. } => self.remaining(c),
+        }
+    }
+
+    /// Updates the current remaining time after consuming the given amount of time for the given player.
+    ///
+    /// Returns false if the given player runs out of time, true otherwise.
+    pub fn consume(&mut self, c: Color, d: Duration) -> bool {
+        if d > self.allotted_time(c) {
+            return false;
+        }
+
+        match *self {
+            TimeControl::Byoyomi {
+                ref mut black_time,
+                ref mut white_time,
+                ..
+            } => {
+                let stm_time = if c == Color::Black {
+                    black_time
+                } else {
+                    white_time
+                };
+                *stm_time = stm_time.saturating_sub(d);
+            }
+            TimeControl::FischerClock {
+                ref mut black_time,
+                ref mut white_time,
+                black_inc,
+                white_inc,
+            } => {
+                let (stm_time, inc) = if c == Color::Black {
+                    (black_time, black_inc)
+                } else {
+                    (white_time, white_inc)
+                };
+                *stm_time = *stm_time + inc - d;
+            }
+            TimeControl::MovesPerPeriod {
+                ref mut black_time,
+                ref mut white_time,
+                moves,
+                period_time,
+                ref mut black_played,
+                ref mut white_played,
+            } => {
+                let (stm_time, played) = if c == Color::Black {
+                    (black_time, black_played)
+                } else {
+                    (white_time, white_played)
+                };
+                *stm_time -= d;
+                *played += 1;
+                if *played >= moves {
+                    *played = 0;
+                    *stm_time += period_time;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Sets the remaining time of the given player to zero, e.g. when the player lost on time.
+    pub fn expire(&mut self, c: Color) {
+        match *self {
+            TimeControl::Byoyomi {
+                ref mut black_time,
+                ref mut white_time,
+                ..
+            }
+            | TimeControl::FischerClock {
+                ref mut black_time,
+                ref mut white_time,
+                ..
+            }
+            | TimeControl::MovesPerPeriod {
+                ref mut black_time,
+                ref mut white_time,
+                ..
+            } => {
+                let stm_time = if c == Color::Black {
+                    black_time
+                } else {
+                    white_time
+                };
+                *stm_time = Duration::from_secs(0);
+            }
+        }
+    }
+
+    /// Returns a description of the schedule of the given player.
+    fn describe(&self, c: Color) -> String {
+        let secs = |d: Duration| d.as_secs_f64();
+        let main = secs(self.remaining(c));
+        let is_black = c == Color::Black;
+
+        match *self {
+            TimeControl::Byoyomi {
+                black_byoyomi,
+                white_byoyomi,
+                ..
+            } => {
+                let byoyomi = if is_black {
+                    black_byoyomi
+                } else {
+                    white_byoyomi
+                };
+                format!("{main}s + {}s byoyomi", secs(byoyomi))
+            }
+            TimeControl::FischerClock {
+                black_inc,
+                white_inc,
+                ..
+            } => {
+                let inc = if is_black { black_inc } else { white_inc };
+                format!("{main}s + {}s increment", secs(inc))
+            }
+            TimeControl::MovesPerPeriod {
+                moves, period_time, ..
+            } => format!("{main}s, then {}s every {moves} moves", secs(period_time)),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let black = self.describe(Color::Black);
+        let white = self.describe(Color::White);
+
+        if black == white {
+            write!(f, "{black}")
+        } else {
+            write!(f, "black {black}, white {white}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn byoyomi() {
+        let mut time = TimeControl::Byoyomi {
+            black_time: secs(60),
+            white_time: secs(0),
+            black_byoyomi: secs(10),
+            white_byoyomi: secs(5),
+        };
+
+        assert_eq!(secs(70), time.allotted_time(Color::Black));
+        assert!(time.consume(Color::Black, secs(50)));
+        assert_eq!(secs(10), time.black_time());
+
+        // Byoyomi is used after the main time runs out, and starts over for each move.
+        assert!(time.consume(Color::Black, secs(15)));
+        assert_eq!(secs(0), time.black_time());
+        assert_eq!(secs(10), time.allotted_time(Color::Black));
+        assert!(time.consume(Color::Black, secs(10)));
+        assert!(time.consume(Color::Black, secs(10)));
+
+        assert!(time.consume(Color::White, secs(5)));
+        assert!(!time.consume(Color::White, Duration::from_millis(5001)));
+    }
+
+    #[test]
+    fn increment() {
+        let mut time = TimeControl::FischerClock {
+            black_time: secs(60),
+            white_time: secs(30),
+            black_inc: secs(10),
+            white_inc: secs(0),
+        };
+
+        // The increment is added before the move is charged.
+        assert_eq!(secs(70), time.allotted_time(Color::Black));
+        assert!(time.consume(Color::Black, secs(5)));
+        assert_eq!(secs(65), time.black_time());
+        assert!(time.consume(Color::Black, secs(75)));
+        assert_eq!(secs(0), time.black_time());
+        assert!(time.consume(Color::Black, secs(10)));
+        assert_eq!(secs(0), time.black_time());
+
+        // The clock is left as is when the player runs out of time.
+        assert!(!time.consume(Color::White, secs(31)));
+        assert_eq!(secs(30), time.white_time());
+    }
+
+    #[test]
+    fn moves_per_period() {
+        let mut time = TimeControl::moves_per_period(secs(60), secs(60), 2, secs(30));
+
+        assert_eq!(Some(2), time.moves_to_go(Color::Black));
+        assert!(time.consume(Color::Black, secs(20)));
+        assert_eq!(Some(1), time.moves_to_go(Color::Black));
+        assert_eq!(secs(40), time.black_time());
+
+        // The period time is added after the last move of the period.
+        assert!(time.consume(Color::Black, secs(40)));
+        assert_eq!(Some(2), time.moves_to_go(Color::Black));
+        assert_eq!(secs(30), time.black_time());
+        assert_eq!(secs(30), time.allotted_time(Color::Black));
+        assert!(!time.consume(Color::Black, secs(31)));
+
+        // Each player has its own period.
+        assert_eq!(Some(2), time.moves_to_go(Color::White));
+        assert_eq!(secs(60), time.white_time());
+    }
+
+    #[test]
+    fn expire() {
+        let mut time = TimeControl::FischerClock {
+            black_time: secs(60),
+            white_time: secs(60),
+            black_inc: secs(10),
+            white_inc: secs(10),
+        };
+
+        time.expire(Color::White);
+        assert_eq!(secs(60), time.black_time());
+        assert_eq!(secs(0), time.white_time());
+    }
+
+    #[test]
+    fn display() {
+        let time = TimeControl::Byoyomi {
+            black_time: secs(60),
+            white_time: secs(60),
+            black_byoyomi: Duration::from_millis(500),
+            white_byoyomi: Duration::from_millis(500),
+        };
+        assert_eq!("60s + 0.5s byoyomi", time.to_string());
+
+        let time = TimeControl::FischerClock {
+            black_time: secs(60),
+            white_time: secs(30),
+            black_inc: secs(1),
+            white_inc: secs(1),
+        };
+        assert_eq!(
+            "black 60s + 1s increment, white 30s + 1s increment",
+            time.to_string()
+        );
+
+        let time = TimeControl::moves_per_period(secs(600), secs(600), 40, secs(300));
+        assert_eq!("600s, then 300s every 40 moves", time.to_string());
+    }
+}
@@ -1,27 +1,201 @@
-use shogi::TimeControl;
+use std::fmt;
 use std::fs::File;
-use std::io::{Error, Read};
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
-use toml::Value;
+use toml::{Table, Value};
+
+use crate::clock::TimeControl;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum DisplayMode {
     Board,
     Command,
     Csa,
+    Jkf,
+    /// Writes each event of the match as a line of JSON.
+    Jsonl,
+    Ki2,
+    Kif,
+    Sfen,
     Simple,
 }
 
+impl DisplayMode {
+    /// Returns true if the mode writes game records, which can be written to files.
+    pub fn is_record(self) -> bool {
+        matches!(
+            self,
+            DisplayMode::Csa
+                | DisplayMode::Jkf
+                | DisplayMode::Jsonl
+                | DisplayMode::Ki2
+                | DisplayMode::Kif
+                | DisplayMode::Sfen
+        )
+    }
+}
+
+impl FromStr for DisplayMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DisplayMode, Error> {
+        match s {
+            "board" => Ok(DisplayMode::Board),
+            "command" | "usi" => Ok(DisplayMode::Command),
+            "csa" => Ok(DisplayMode::Csa),
+            "jkf" => Ok(DisplayMode::Jkf),
+            "jsonl" => Ok(DisplayMode::Jsonl),
+            "ki2" => Ok(DisplayMode::Ki2),
+            "kif" => Ok(DisplayMode::Kif),
+            "sfen" => Ok(DisplayMode::Sfen),
+            "simple" => Ok(DisplayMode::Simple),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown output format \"{s}\""),
+            )),
+        }
+    }
+}
+
+/// Represents an output written in addition to the display, e.g. "csa:games/".
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OutputConfig {
+    pub mode: DisplayMode,
+    /// A directory to write a file per game if it ends with a path separator or exists,
+    /// otherwise a file to write all games.
+    pub path: PathBuf,
+}
+
+impl OutputConfig {
+    /// Parses an output of game records, e.g. "csa:games/" or "sfen:games.txt".
+    pub fn record(s: &str) -> Result<OutputConfig, Error> {
+        OutputConfig::parse(
+            s,
+            DisplayMode::is_record,
+            "csa, jkf, jsonl, ki2, kif or sfen",
+        )
+    }
+
+    /// Parses an output of logs written to a directory, e.g. "usi:logs/".
+    pub fn log(s: &str) -> Result<OutputConfig, Error> {
+        OutputConfig::parse(s, |mode| mode == DisplayMode::Command, "usi")
+    }
+
+    fn parse(
+        s: &str,
+        accepts: impl Fn(DisplayMode) -> bool,
+        expected: &str,
+    ) -> Result<OutputConfig, Error> {
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid output \"{s}\" (expected \"<format>:<path>\" with {expected})"),
+            )
+        };
+
+        let (format, path) = s.split_once(':').ok_or_else(invalid)?;
+        let mode = format.parse::<DisplayMode>().map_err(|_| invalid())?;
+        if !accepts(mode) || path.is_empty() {
+            return Err(invalid());
+        }
+
+        let output = OutputConfig {
+            mode,
+            path: PathBuf::from(path),
+        };
+        // Events of the whole match are written to one file, while logs are written to files
+        // per game and engine.
+        let is_dir = output.dir().is_some();
+        if (mode == DisplayMode::Jsonl && is_dir) || (mode == DisplayMode::Command && !is_dir) {
+            return Err(invalid());
+        }
+        Ok(output)
+    }
+
+    /// Returns the directory to write a file per game, or None if all games are written to one file.
+    pub fn dir(&self) -> Option<&Path> {
+        let s = self.path.to_string_lossy();
+        if s.ends_with('/') || s.ends_with(std::path::MAIN_SEPARATOR) || self.path.is_dir() {
+            Some(&self.path)
+        } else {
+            None
+        }
+    }
+}
+
+/// Represents how the time spent on a move is charged to the clock.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ClockMode {
+    /// Charges the exact elapsed time.
+    #[default]
+    Exact,
+    /// Charges whole seconds rounded down with a minimum of 1 second per move, as CSA servers do.
+    Csa,
+}
+
+impl ClockMode {
+    /// Returns the time charged to the clock for a move which took `elapsed`.
+    pub fn charge(&self, elapsed: Duration) -> Duration {
+        match *self {
+            ClockMode::Exact => elapsed,
+            ClockMode::Csa => Duration::from_secs(elapsed.as_secs().max(1)),
+        }
+    }
+}
+
+impl FromStr for ClockMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ClockMode, Error> {
+        match s {
+            "exact" => Ok(ClockMode::Exact),
+            "csa" => Ok(ClockMode::Csa),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown clock_mode \"{s}\" (expected \"exact\" or \"csa\")"),
+            )),
+        }
+    }
+}
+
+/// Represents how long an engine searches for each move.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum SearchLimit {
+    /// Searches within the remaining time on the clock.
+    #[default]
+    Clock,
+    Depth(u32),
+    Nodes(u64),
+    MoveTime(Duration),
+}
+
+impl fmt::Display for SearchLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SearchLimit::Clock => write!(f, "clock"),
+            SearchLimit::Depth(n) => write!(f, "depth {n}"),
+            SearchLimit::Nodes(n) => write!(f, "nodes {n}"),
+            SearchLimit::MoveTime(t) => write!(f, "movetime {}", t.as_millis()),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct EngineConfig {
     pub engine_path: String,
     pub working_dir: String,
     pub ponder: bool,
+    /// Whether or not to send `movestogo` with moves-per-period time controls.
+    pub movestogo: bool,
     pub options: Vec<(String, String)>,
+    pub time: EngineTimeConfig,
+    pub limit: SearchLimit,
 }
 
 impl EngineConfig {
-    fn merge(&mut self, value: &Value) {
+    fn merge(&mut self, value: &Value) -> Result<(), Error> {
         if let Some(engine_path) = value.get("engine_path").and_then(|v| v.as_str()) {
             self.engine_path = engine_path.to_string();
         }
@@ -34,11 +208,103 @@ impl EngineConfig {
             self.ponder = flag;
         }
 
+        if let Some(flag) = value.get("movestogo").and_then(|v| v.as_bool()) {
+            self.movestogo = flag;
+        }
+
+        if let Some(time_control) = value.get("time_control") {
+            self.time.merge(time_control)?;
+        }
+
+        let limits = [
+            get_integer(value, "depth")?.map(SearchLimit::Depth),
+            get_integer(value, "nodes")?.map(SearchLimit::Nodes),
+            get_duration(value, "movetime")?.map(SearchLimit::MoveTime),
+        ];
+        let mut limits = limits.iter().flatten();
+        if let Some(limit) = limits.next() {
+            if limits.next().is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "only one of \"depth\", \"nodes\" and \"movetime\" can be specified",
+                ));
+            }
+            self.limit = *limit;
+        }
+
         if let Some(options) = value.get("options").and_then(|v| v.as_table()) {
             for (name, value) in options.iter() {
-                self.options.push((name.to_string(), value.to_string()));
+                self.options
+                    .push((name.to_string(), to_option_value(name, value)?));
             }
         }
+
+        Ok(())
+    }
+}
+
+/// Converts a TOML value into the string sent as a value of `setoption` command.
+///
+/// Booleans are sent as `check` values, integers as `spin` values, and strings are sent as is
+/// for `string`, `filename` and `combo` options. Floats keep the decimal point so that they are
+/// rejected for `spin` options instead of being truncated.
+/// An empty string is sent as `<empty>` as defined in the USI protocol.
+/// Values are checked against the type declared by the engine when the engine starts.
+fn to_option_value(name: &str, value: &Value) -> Result<String, Error> {
+    match *value {
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Float(f) if f.is_finite() => Ok(format!("{f:?}")),
+        Value::String(ref s) if s.is_empty() => Ok("<empty>".to_string()),
+        Value::String(ref s) => Ok(s.to_string()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported value for the option \"{name}\": {value}"),
+        )),
+    }
+}
+
+/// Returns the integer of the given key, failing if it is out of the range of `T`, e.g. negative.
+fn get_integer<T: TryFrom<i64>>(value: &Value, key: &str) -> Result<Option<T>, Error> {
+    match value.get(key).and_then(|v| v.as_integer()) {
+        Some(n) => T::try_from(n).map(Some).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("\"{key}\" is out of range: {n}"),
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Returns the duration of the given key in milliseconds.
+fn get_duration(value: &Value, key: &str) -> Result<Option<Duration>, Error> {
+    Ok(get_integer(value, key)?.map(Duration::from_millis))
+}
+
+/// Time settings for a specific engine, which take precedence over `[time_control]`.
+#[derive(Debug, Default)]
+pub struct EngineTimeConfig {
+    pub time: Option<Duration>,
+    pub inc: Option<Duration>,
+    pub byoyomi: Option<Duration>,
+}
+
+impl EngineTimeConfig {
+    fn merge(&mut self, value: &Value) -> Result<(), Error> {
+        if let Some(s) = value.as_str() {
+            let (time, inc, byoyomi) = parse_time_control(s)?;
+            self.time = Some(time);
+            self.inc = inc;
+            self.byoyomi = byoyomi;
+            return Ok(());
+        }
+
+        self.time = get_duration(value, "time")?;
+        self.inc = get_duration(value, "inc")?;
+        self.byoyomi = get_duration(value, "byoyomi")?;
+
+        Ok(())
     }
 }
 
@@ -46,55 +312,190 @@ impl EngineConfig {
 pub struct TimeControlConfig {
     pub black_time: Duration,
     pub white_time: Duration,
-    pub byoyomi: Option<Duration>,
+    pub black_byoyomi: Option<Duration>,
+    pub white_byoyomi: Option<Duration>,
     pub black_inc: Option<Duration>,
     pub white_inc: Option<Duration>,
+    /// The number of moves in each period of a moves-per-period time control.
+    pub period_moves: Option<u32>,
+    /// The time added to the clock after every `period_moves` moves.
+    pub period_time: Option<Duration>,
 }
 
 impl TimeControlConfig {
-    fn merge(&mut self, value: &Value) {
-        if let Some(btime) = value.get("black_time").and_then(|v| v.as_integer()) {
-            self.black_time = Duration::from_millis(btime as u64);
+    fn merge(&mut self, value: &Value) -> Result<(), Error> {
+        if let Some(btime) = get_duration(value, "black_time")? {
+            self.black_time = btime;
         }
 
-        if let Some(wtime) = value.get("white_time").and_then(|v| v.as_integer()) {
-            self.white_time = Duration::from_millis(wtime as u64);
+        if let Some(wtime) = get_duration(value, "white_time")? {
+            self.white_time = wtime;
         }
 
-        self.byoyomi = value
-            .get("byoyomi")
-            .and_then(|v| v.as_integer())
-            .map(|v| Duration::from_millis(v as u64));
+        // "byoyomi" applies to both players unless specified for each player.
+        let byoyomi = get_duration(value, "byoyomi")?;
+        self.black_byoyomi = get_duration(value, "black_byoyomi")?.or(byoyomi);
+        self.white_byoyomi = get_duration(value, "white_byoyomi")?.or(byoyomi);
+
+        self.black_inc = get_duration(value, "black_inc")?;
+        self.white_inc = get_duration(value, "white_inc")?;
 
-        self.black_inc = value
-            .get("black_inc")
-            .and_then(|v| v.as_integer())
-            .map(|v| Duration::from_millis(v as u64));
+        self.period_moves = get_integer(value, "period_moves")?;
+        self.period_time = get_duration(value, "period_time")?;
 
-        self.white_inc = value
-            .get("white_inc")
-            .and_then(|v| v.as_integer())
-            .map(|v| Duration::from_millis(v as u64));
+        Ok(())
     }
 
-    pub fn to_time_control(&self) -> TimeControl {
-        if let Some(byoyomi) = self.byoyomi {
-            TimeControl::Byoyomi {
-                black_time: self.black_time,
-                white_time: self.white_time,
-                byoyomi,
+    /// Returns the time control of a game with the given settings of each engine applied.
+    ///
+    /// Fails if the settings are conflicting, e.g. byoyomi combined with increments.
+    pub fn to_time_control(
+        &self,
+        black: &EngineTimeConfig,
+        white: &EngineTimeConfig,
+    ) -> Result<TimeControl, Error> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let non_zero = |d: Option<Duration>| d.filter(|d| !d.is_zero());
+        // Increment and byoyomi of an engine replace those in [time_control] together,
+        // so that an engine can switch to byoyomi while [time_control] specifies increments.
+        let schedule = |engine: &EngineTimeConfig, inc, byoyomi| {
+            if engine.inc.is_some() || engine.byoyomi.is_some() {
+                (non_zero(engine.inc), non_zero(engine.byoyomi))
+            } else {
+                (non_zero(inc), non_zero(byoyomi))
             }
-        } else {
-            TimeControl::FischerClock {
-                black_time: self.black_time,
-                white_time: self.white_time,
-                black_inc: self.black_inc.unwrap_or_else(|| Duration::from_secs(0)),
-                white_inc: self.white_inc.unwrap_or_else(|| Duration::from_secs(0)),
+        };
+
+        let black_time = black.time.unwrap_or(self.black_time);
+        let white_time = white.time.unwrap_or(self.white_time);
+        let (black_inc, black_byoyomi) = schedule(black, self.black_inc, self.black_byoyomi);
+        let (white_inc, white_byoyomi) = schedule(white, self.white_inc, self.white_byoyomi);
+
+        let sides = [
+            ("black", black_time, black_inc, black_byoyomi),
+            ("white", white_time, white_inc, white_byoyomi),
+        ];
+        for (name, time, inc, byoyomi) in sides {
+            if inc.is_some() && byoyomi.is_some() {
+                return Err(invalid(format!(
+                    "byoyomi and increment can not be combined for the {name} player"
+                )));
+            }
+            if time.is_zero() && inc.is_none() && byoyomi.is_none() {
+                return Err(invalid(format!("no time is given to the {name} player")));
+            }
+        }
+
+        let has_inc = black_inc.is_some() || white_inc.is_some();
+        let has_byoyomi = black_byoyomi.is_some() || white_byoyomi.is_some();
+
+        if let Some(moves) = self.period_moves {
+            let period_time = self.period_time.ok_or_else(|| {
+                invalid("\"period_time\" must be specified with \"period_moves\"".to_string())
+            })?;
+            if moves == 0 {
+                return Err(invalid(
+                    "\"period_moves\" must be greater than 0".to_string(),
+                ));
+            }
+            if has_byoyomi || has_inc {
+                return Err(invalid(
+                    "moves per period can not be combined with byoyomi or increments".to_string(),
+                ));
             }
+
+            return Ok(TimeControl::moves_per_period(
+                black_time,
+                white_time,
+                moves,
+                period_time,
+            ));
+        }
+
+        if has_byoyomi && has_inc {
+            return Err(invalid(
+                "byoyomi and increment can not be mixed between the players".to_string(),
+            ));
+        }
+
+        let zero = Duration::from_secs(0);
+        if has_byoyomi {
+            Ok(TimeControl::Byoyomi {
+                black_time,
+                white_time,
+                black_byoyomi: black_byoyomi.unwrap_or(zero),
+                white_byoyomi: white_byoyomi.unwrap_or(zero),
+            })
+        } else {
+            Ok(TimeControl::FischerClock {
+                black_time,
+                white_time,
+                black_inc: black_inc.unwrap_or(zero),
+                white_inc: white_inc.unwrap_or(zero),
+            })
         }
     }
 }
 
+/// Parses a compact time control string in seconds, e.g. `60+1` (Fischer),
+/// `300/10b` (main time plus byoyomi) or `10b` (byoyomi only).
+///
+/// Returns a tuple of main time, increment and byoyomi.
+fn parse_time_control(s: &str) -> Result<(Duration, Option<Duration>, Option<Duration>), Error> {
+    let invalid = |reason: String| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "invalid time control \"{s}\": {reason} \
+                 (expected \"<main>+<inc>\", \"<main>/<byoyomi>b\" or \"<byoyomi>b\" in seconds)"
+            ),
+        )
+    };
+    let parse_secs = |v: &str| {
+        let v = v.trim();
+        // Signs are rejected so that "60++1" is not read as 60 seconds plus 1.
+        let unsigned = v.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+        match v.parse::<f64>() {
+            Ok(secs) if unsigned => Duration::try_from_secs_f64(secs)
+                .map_err(|e| invalid(format!("\"{v}\" is out of range: {e}"))),
+            _ => Err(invalid(format!("\"{v}\" is not a valid number of seconds"))),
+        }
+    };
+
+    let s = s.trim();
+    if let Some(byoyomi) = s.strip_suffix('b') {
+        match byoyomi.split_once('/') {
+            Some((main, byoyomi)) => Ok((parse_secs(main)?, None, Some(parse_secs(byoyomi)?))),
+            None => Ok((Duration::from_secs(0), None, Some(parse_secs(byoyomi)?))),
+        }
+    } else if s.contains('/') {
+        Err(invalid("byoyomi must end with 'b'".to_string()))
+    } else {
+        match s.split_once('+') {
+            Some((main, inc)) => Ok((parse_secs(main)?, Some(parse_secs(inc)?), None)),
+            None => Ok((parse_secs(s)?, None, None)),
+        }
+    }
+}
+
+impl FromStr for TimeControlConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TimeControlConfig, Error> {
+        let (time, inc, byoyomi) = parse_time_control(s)?;
+        Ok(TimeControlConfig {
+            black_time: time,
+            white_time: time,
+            black_byoyomi: byoyomi,
+            white_byoyomi: byoyomi,
+            black_inc: inc,
+            white_inc: inc,
+            period_moves: None,
+            period_time: None,
+        })
+    }
+}
+
 impl Default for TimeControlConfig {
     fn default() -> TimeControlConfig {
         // Default values are derived from the rules of WCSC26.
@@ -102,22 +503,121 @@ impl Default for TimeControlConfig {
         TimeControlConfig {
             black_time: Duration::from_secs(600),
             white_time: Duration::from_secs(600),
-            byoyomi: None,
+            black_byoyomi: None,
+            white_byoyomi: None,
             black_inc: Some(Duration::from_secs(10)),
             white_inc: Some(Duration::from_secs(10)),
+            period_moves: None,
+            period_time: None,
         }
     }
 }
 
+/// Values substituted for the placeholders of a file name template.
+#[derive(Debug, Default)]
+pub struct FilenameFields<'a> {
+    /// The start date of the game in "YYYYMMDD".
+    pub date: &'a str,
+    /// The start time of the game in "HHMMSS".
+    pub time: &'a str,
+    pub game: u32,
+    pub black: &'a str,
+    pub white: &'a str,
+    pub ext: &'a str,
+}
+
+/// Represents a template of file names of game records, e.g. "{date}_{game:04}_{black}_vs_{white}.csa".
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FilenameTemplate(String);
+
+impl FilenameTemplate {
+    /// Returns the file name with the placeholders replaced by the given values.
+    pub fn render(&self, fields: &FilenameFields) -> Result<String, Error> {
+        let invalid = |reason: String| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid filename template \"{}\": {reason}", self.0),
+            )
+        };
+
+        let mut name = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed \"{\"".to_string()))?;
+            let placeholder = &rest[start + 1..start + end];
+            rest = &rest[start + end + 1..];
+
+            let (key, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+            let value = match key {
+                "date" => fields.date.to_string(),
+                "time" => fields.time.to_string(),
+                "game" => fields.game.to_string(),
+                "black" => fields.black.to_string(),
+                "white" => fields.white.to_string(),
+                "ext" => fields.ext.to_string(),
+                _ => return Err(invalid(format!("unknown placeholder \"{{{key}}}\""))),
+            };
+            if spec.is_empty() {
+                name.push_str(&value);
+                continue;
+            }
+
+            // Only zero padding of numbers is supported, e.g. "{game:04}".
+            let width = spec
+                .strip_prefix('0')
+                .and_then(|w| w.parse::<usize>().ok())
+                .filter(|_| key == "game")
+                .ok_or_else(|| invalid(format!("unsupported format \"{{{placeholder}}}\"")))?;
+            name.push_str(&format!("{value:0>width$}"));
+        }
+        name.push_str(rest);
+
+        Ok(name)
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<FilenameTemplate, Error> {
+        let template = FilenameTemplate(s.to_string());
+        template.render(&FilenameFields::default())?;
+        Ok(template)
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> FilenameTemplate {
+        FilenameTemplate("{date}_{time}_{game:04}_{black}_vs_{white}.{ext}".to_string())
+    }
+}
+
 #[derive(Debug)]
 pub struct MatchConfig {
     pub num_games: u32,
     pub max_ply: Option<u16>,
     pub initial_pos: Option<String>,
+    /// Overruns up to this margin are tolerated to absorb process and scheduling lag.
+    pub time_margin: Duration,
+    pub clock_mode: ClockMode,
     pub black_engine: EngineConfig,
     pub white_engine: EngineConfig,
     pub time: TimeControlConfig,
     pub display: DisplayMode,
+    /// Game records are written to a file per game in this directory instead of stdout if set.
+    pub output_dir: Option<PathBuf>,
+    pub filename: FilenameTemplate,
+    /// Appends the termination reason and the engine names to each game in SFEN records.
+    pub sfen_details: bool,
+    /// Game records written in addition to the display.
+    pub records: Vec<OutputConfig>,
+    /// Logs written in addition to the display.
+    pub logs: Vec<OutputConfig>,
+    /// The final results of the match are written to this file in JSON if set.
+    pub results_json: Option<PathBuf>,
 }
 
 impl MatchConfig {
@@ -126,36 +626,92 @@ impl MatchConfig {
         let mut buf = String::new();
         f.read_to_string(&mut buf)?;
 
-        let value = buf.parse::<Value>().unwrap();
-
-        self.num_games = value
-            .get("num_games")
-            .and_then(|v| v.as_integer())
-            .map(|v| v as u32)
-            .unwrap_or(1);
-        self.max_ply = value
-            .get("max_ply")
-            .and_then(|v| v.as_integer())
-            .map(|v| v as u16);
+        self.load_str(&buf)
+    }
+
+    /// Loads settings from a configuration in TOML format.
+    pub fn load_str(&mut self, config: &str) -> Result<(), Error> {
+        let value = config
+            .parse::<Table>()
+            .map(Value::Table)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        self.num_games = get_integer(&value, "num_games")?.unwrap_or(1);
+        self.max_ply = get_integer(&value, "max_ply")?;
         self.initial_pos = value
             .get("initial_pos")
             .and_then(|v| v.as_str())
             .map(|v| v.to_string());
+        self.time_margin = get_duration(&value, "time_margin")?.unwrap_or_default();
+        self.clock_mode = match value.get("clock_mode").and_then(|v| v.as_str()) {
+            Some(mode) => mode.parse()?,
+            None => ClockMode::Exact,
+        };
+        self.output_dir = value
+            .get("output_dir")
+            .and_then(|v| v.as_str())
+            .map(PathBuf::from);
+        if let Some(filename) = value.get("filename").and_then(|v| v.as_str()) {
+            self.filename = filename.parse()?;
+        }
+        self.sfen_details = value
+            .get("sfen_details")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let outputs = |key: &str| {
+            value
+                .get(key)
+                .and_then(|v| v.as_array())
+                .map(|items| items.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+        self.records = outputs("records")
+            .into_iter()
+            .map(OutputConfig::record)
+            .collect::<Result<_, _>>()?;
+        self.logs = outputs("logs")
+            .into_iter()
+            .map(OutputConfig::log)
+            .collect::<Result<_, _>>()?;
+        self.results_json = value
+            .get("results_json")
+            .and_then(|v| v.as_str())
+            .map(PathBuf::from);
 
         if let Some(black) = value.get("black") {
-            self.black_engine.merge(black);
+            self.black_engine.merge(black)?;
         }
 
         if let Some(white) = value.get("white") {
-            self.white_engine.merge(white);
+            self.white_engine.merge(white)?;
         }
 
         if let Some(time_control) = value.get("time_control") {
-            self.time.merge(time_control);
+            match time_control.as_str() {
+                Some(s) => self.time = s.parse()?,
+                None => self.time.merge(time_control)?,
+            }
         }
 
+        self.time_control()?;
+
         Ok(())
     }
+
+    /// Replaces [time_control] with a compact time control string, e.g. "60+1".
+    ///
+    /// Settings of each engine still take precedence, and the result is validated with them.
+    pub fn override_time_control(&mut self, s: &str) -> Result<(), Error> {
+        self.time = s.parse()?;
+        self.time_control()?;
+        Ok(())
+    }
+
+    /// Returns the time control of a game, where settings of each engine follow the engine.
+    pub fn time_control(&self) -> Result<TimeControl, Error> {
+        self.time
+            .to_time_control(&self.black_engine.time, &self.white_engine.time)
+    }
 }
 
 impl Default for MatchConfig {
@@ -166,10 +722,339 @@ impl Default for MatchConfig {
             num_games: 1,
             max_ply: Some(256),
             initial_pos: None,
+            time_margin: Duration::from_secs(0),
+            clock_mode: ClockMode::Exact,
             black_engine: Default::default(),
             white_engine: Default::default(),
             time: Default::default(),
             display: DisplayMode::Board,
+            output_dir: None,
+            filename: Default::default(),
+            sfen_details: false,
+            records: Vec::new(),
+            logs: Vec::new(),
+            results_json: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi::Color;
+
+    fn convert(s: &str) -> Result<String, Error> {
+        let table = s.parse::<Table>().unwrap();
+        to_option_value("Opt", table.get("v").unwrap())
+    }
+
+    #[test]
+    fn option_value_check() {
+        assert_eq!("true", convert("v = true").unwrap());
+        assert_eq!("false", convert("v = false").unwrap());
+    }
+
+    #[test]
+    fn option_value_spin() {
+        assert_eq!("128", convert("v = 128").unwrap());
+        assert_eq!("-5", convert("v = -5").unwrap());
+        assert_eq!("0", convert("v = 0").unwrap());
+    }
+
+    #[test]
+    fn option_value_float() {
+        assert_eq!("0.5", convert("v = 0.5").unwrap());
+        assert_eq!("2.0", convert("v = 2.0").unwrap());
+        assert!(convert("v = nan").is_err());
+        assert!(convert("v = inf").is_err());
+    }
+
+    #[test]
+    fn option_value_string() {
+        assert_eq!("eval", convert("v = \"eval\"").unwrap());
+        assert_eq!(
+            "book/user book.db",
+            convert("v = \"book/user book.db\"").unwrap()
+        );
+        assert_eq!("<empty>", convert("v = \"\"").unwrap());
+    }
+
+    #[test]
+    fn option_value_unsupported() {
+        assert!(convert("v = [1, 2]").is_err());
+        assert!(convert("v = { a = 1 }").is_err());
+        assert!(convert("v = 1979-05-27").is_err());
+    }
+
+    #[test]
+    fn engine_options() {
+        let value = r#"
+            engine_path = "/path/to/engine"
+            [options]
+            EvalDir = "eval"
+            USI_Hash = 256
+            OwnBook = false
+        "#
+        .parse::<Table>()
+        .map(Value::Table)
+        .unwrap();
+
+        let mut config = EngineConfig::default();
+        config.merge(&value).unwrap();
+
+        assert!(config
+            .options
+            .contains(&("EvalDir".to_string(), "eval".to_string())));
+        assert!(config
+            .options
+            .contains(&("USI_Hash".to_string(), "256".to_string())));
+        assert!(config
+            .options
+            .contains(&("OwnBook".to_string(), "false".to_string())));
+    }
+
+    #[test]
+    fn engine_time_control() {
+        let value = r#"
+            engine_path = "/path/to/engine"
+            [time_control]
+            time = 1200000
+        "#
+        .parse::<Table>()
+        .map(Value::Table)
+        .unwrap();
+
+        let mut config = MatchConfig::default();
+        config.black_engine.merge(&value).unwrap();
+        config.white_engine.time.inc = Some(Duration::from_secs(5));
+
+        match config.time_control().unwrap() {
+            TimeControl::FischerClock {
+                black_time,
+                white_time,
+                black_inc,
+                white_inc,
+            } => {
+                assert_eq!(Duration::from_secs(1200), black_time);
+                assert_eq!(Duration::from_secs(600), white_time);
+                assert_eq!(Duration::from_secs(10), black_inc);
+                assert_eq!(Duration::from_secs(5), white_inc);
+            }
+            _ => unreachable!(),
+        }
+
+        config.black_engine.time.byoyomi = Some(Duration::from_secs(10));
+        assert!(config.time_control().is_err());
+    }
+
+    #[test]
+    fn time_margin() {
+        let mut config = MatchConfig::default();
+        config.load_str("time_margin = 200\n").unwrap();
+        assert_eq!(Duration::from_millis(200), config.time_margin);
+
+        assert!(config.load_str("time_margin = -1\n").is_err());
+    }
+
+    #[test]
+    fn csa_clock_mode() {
+        let charge = |ms: u64| ClockMode::Csa.charge(Duration::from_millis(ms));
+        assert_eq!(Duration::from_secs(1), charge(0));
+        assert_eq!(Duration::from_secs(1), charge(1999));
+        assert_eq!(Duration::from_secs(2), charge(2000));
+        assert_eq!(
+            Duration::from_millis(1500),
+            ClockMode::Exact.charge(Duration::from_millis(1500))
+        );
+        assert!("floodgate".parse::<ClockMode>().is_err());
+    }
+
+    #[test]
+    fn time_control_combination() {
+        let parse = |s: &str| {
+            let value = s.parse::<Table>().map(Value::Table).unwrap();
+            let mut config = MatchConfig::default();
+            config.time.merge(&value)?;
+            config.time_control()
+        };
+
+        match parse("black_time = 0\nwhite_time = 0\nbyoyomi = 10000\nwhite_byoyomi = 5000") {
+            Ok(TimeControl::Byoyomi {
+                black_byoyomi,
+                white_byoyomi,
+                ..
+            }) => {
+                assert_eq!(Duration::from_secs(10), black_byoyomi);
+                assert_eq!(Duration::from_secs(5), white_byoyomi);
+            }
+            _ => unreachable!(),
+        }
+
+        // Zero increments do not conflict with byoyomi.
+        assert!(parse("byoyomi = 10000\nblack_inc = 0").is_ok());
+        assert!(parse("byoyomi = 10000\nblack_inc = 1000").is_err());
+        assert!(parse("black_byoyomi = 10000\nwhite_inc = 1000").is_err());
+        assert!(parse("black_time = 0\nwhite_time = 0\nblack_byoyomi = 10000").is_err());
+        assert!(parse("black_time = 0\nwhite_time = 60000").is_err());
+        assert!(parse("black_time = 0\nwhite_time = 0\nbyoyomi = -10000").is_err());
+    }
+
+    #[test]
+    fn moves_per_period() {
+        let value = r#"
+            black_time = 600000
+            white_time = 600000
+            period_moves = 40
+            period_time = 300000
+        "#
+        .parse::<Table>()
+        .map(Value::Table)
+        .unwrap();
+
+        let mut config = MatchConfig::default();
+        config.time.merge(&value).unwrap();
+
+        let mut time = config.time_control().unwrap();
+        assert_eq!(Some(40), time.moves_to_go(Color::Black));
+        for _ in 0..39 {
+            assert!(time.consume(Color::Black, Duration::from_secs(10)));
+        }
+        assert_eq!(Some(1), time.moves_to_go(Color::Black));
+        assert_eq!(Duration::from_secs(210), time.black_time());
+
+        assert!(time.consume(Color::Black, Duration::from_secs(10)));
+        assert_eq!(Some(40), time.moves_to_go(Color::Black));
+        assert_eq!(Duration::from_secs(500), time.black_time());
+        assert!(!time.consume(Color::Black, Duration::from_secs(501)));
+
+        config.time.period_time = None;
+        assert!(config.time_control().is_err());
+
+        config.time.period_time = Some(Duration::from_secs(300));
+        config.white_engine.time.inc = Some(Duration::from_secs(1));
+        assert!(config.time_control().is_err());
+    }
+
+    #[test]
+    fn search_limit() {
+        let parse = |s: &str| {
+            let value = s.parse::<Table>().map(Value::Table).unwrap();
+            let mut config = EngineConfig::default();
+            config.merge(&value).map(|_| config.limit)
+        };
+
+        assert_eq!(SearchLimit::Clock, parse("ponder = false").unwrap());
+        assert_eq!(SearchLimit::Depth(10), parse("depth = 10").unwrap());
+        assert_eq!(SearchLimit::Nodes(100000), parse("nodes = 100000").unwrap());
+        assert_eq!(
+            SearchLimit::MoveTime(Duration::from_millis(500)),
+            parse("movetime = 500").unwrap()
+        );
+        assert!(parse("depth = 10\nnodes = 1000").is_err());
+
+        // Negative values are rejected instead of wrapping around.
+        assert!(parse("nodes = -1").is_err());
+        assert!(parse("movetime = -500").is_err());
+        assert!(parse("[time_control]\nbyoyomi = -1000").is_err());
+    }
+
+    #[test]
+    fn compact_time_control() {
+        let tc = "60+1".parse::<TimeControlConfig>().unwrap();
+        assert_eq!(Duration::from_secs(60), tc.black_time);
+        assert_eq!(Duration::from_secs(60), tc.white_time);
+        assert_eq!(Some(Duration::from_secs(1)), tc.black_inc);
+        assert_eq!(Some(Duration::from_secs(1)), tc.white_inc);
+        assert_eq!(None, tc.black_byoyomi);
+
+        let tc = "300/10b".parse::<TimeControlConfig>().unwrap();
+        assert_eq!(Duration::from_secs(300), tc.black_time);
+        assert_eq!(None, tc.black_inc);
+        assert_eq!(Some(Duration::from_secs(10)), tc.black_byoyomi);
+
+        let tc = "0.5b".parse::<TimeControlConfig>().unwrap();
+        assert_eq!(Duration::from_secs(0), tc.black_time);
+        assert_eq!(Some(Duration::from_millis(500)), tc.black_byoyomi);
+
+        let tc = "600".parse::<TimeControlConfig>().unwrap();
+        assert_eq!(Duration::from_secs(600), tc.black_time);
+        assert_eq!(None, tc.black_inc);
+        assert_eq!(None, tc.black_byoyomi);
+
+        for s in [
+            "", "b", "60+", "+1", "-1+1", "60+1b", "300/10", "a/10b", "60++1", "1e30+1", "inf",
+        ] {
+            assert!(s.parse::<TimeControlConfig>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn override_time_control() {
+        let mut config = MatchConfig::default();
+        config.override_time_control("60+1").unwrap();
+        assert_eq!(Some(Duration::from_secs(1)), config.time.black_inc);
+        assert!(config.override_time_control("1e30+1").is_err());
+        assert!(config.override_time_control("0").is_err());
+
+        // Byoyomi of an engine can not be mixed with increments of the other.
+        config.white_engine.time.byoyomi = Some(Duration::from_secs(10));
+        assert!(config.override_time_control("60+1").is_err());
+        config.override_time_control("60/10b").unwrap();
+    }
+
+    #[test]
+    fn filename_template() {
+        let fields = FilenameFields {
+            date: "20240102",
+            time: "030405",
+            game: 7,
+            black: "A",
+            white: "B",
+            ext: "csa",
+        };
+
+        let template = "{date}_{game:04}_{black}_vs_{white}.csa"
+            .parse::<FilenameTemplate>()
+            .unwrap();
+        assert_eq!(
+            "20240102_0007_A_vs_B.csa",
+            template.render(&fields).unwrap()
+        );
+
+        let template = "{time}-{game}.{ext}".parse::<FilenameTemplate>().unwrap();
+        assert_eq!("030405-7.csa", template.render(&fields).unwrap());
+
+        assert_eq!(
+            "20240102_030405_0007_A_vs_B.csa",
+            FilenameTemplate::default().render(&fields).unwrap()
+        );
+
+        for s in ["{game", "{round}.csa", "{black:04}.csa", "{game:4}.csa"] {
+            assert!(s.parse::<FilenameTemplate>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn outputs() {
+        let output = OutputConfig::record("csa:games/").unwrap();
+        assert_eq!(DisplayMode::Csa, output.mode);
+        assert_eq!(Some(Path::new("games/")), output.dir());
+
+        let output = OutputConfig::record("sfen:games.txt").unwrap();
+        assert_eq!(DisplayMode::Sfen, output.mode);
+        assert_eq!(None, output.dir());
+
+        let output = OutputConfig::record("jsonl:events.jsonl").unwrap();
+        assert_eq!(DisplayMode::Jsonl, output.mode);
+        assert!(OutputConfig::record("jsonl:events/").is_err());
+
+        let output = OutputConfig::log("usi:logs/").unwrap();
+        assert_eq!(DisplayMode::Command, output.mode);
+
+        for s in ["csa", "csa:", "board:games/", "usi:logs/", "pgn:games/"] {
+            assert!(OutputConfig::record(s).is_err(), "{s}");
         }
+        assert!(OutputConfig::log("csa:games/").is_err());
+        assert!(OutputConfig::log("usi:usi.log").is_err());
     }
 }
@@ -1,29 +1,96 @@
-use shogi::{Color, Move, SfenError, TimeControl};
+use shogi::{Color, Move, SfenError};
+use std::mem;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use usi::{
-    BestMoveParams, EngineCommand, EngineOutput, GameOverKind, GuiCommand, InfoParams, ScoreKind,
-    ThinkParams, UsiEngineHandler,
+    BestMoveParams, EngineCommand, EngineOutput, GameOverKind, GuiCommand, InfoParams, OptionKind,
+    ScoreKind, ThinkParams,
 };
 
+use crate::clock::TimeControl;
 use crate::environment::*;
 use crate::error::Error;
-use crate::EngineConfig;
+use crate::process::EngineProcess;
+use crate::profile::{option_default, validate_option, EngineProfile};
+use crate::stats::PonderStatistics;
+use crate::{EngineConfig, SearchLimit};
 
 pub type ReadHookFn = Box<dyn FnMut(&EngineOutput) -> Result<(), Error> + Send>;
 pub type WriteHookFn = Box<dyn FnMut(&GuiCommand, &str) + Send>;
+pub type StderrHookFn = Box<dyn FnMut(&str) + Send>;
 
 #[derive(Default)]
 pub struct ThinkState {
     pub score: i32,
     pondering: Option<Move>,
     pending: Option<()>,
+    ponder_stats: PonderStatistics,
+    info: SearchInfo,
+}
+
+/// Represents statistics of a search reported by `info` commands.
+///
+/// Each `info` command may report only some of the values, so the latest value of each is kept.
+#[derive(Debug, Default, Clone)]
+pub struct SearchInfo {
+    pub depth: Option<i32>,
+    pub seldepth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub hashfull: Option<i32>,
+    pub score: Option<(i32, ScoreKind)>,
+    pub pv: Vec<String>,
+}
+
+impl SearchInfo {
+    /// Returns the score in the notation of `info` commands, e.g. "cp 42" or "mate 5".
+    pub fn score_text(&self) -> Option<String> {
+        let (v, ref kind) = *self.score.as_ref()?;
+
+        Some(match *kind {
+            ScoreKind::CpExact => format!("cp {v}"),
+            ScoreKind::CpLowerbound => format!("cp {v} lowerbound"),
+            ScoreKind::CpUpperbound => format!("cp {v} upperbound"),
+            ScoreKind::MateExact => format!("mate {v}"),
+            ScoreKind::MateSignOnly => format!("mate {}", if v > 0 { "+" } else { "-" }),
+            ScoreKind::MateLowerbound => format!("mate {v} lowerbound"),
+            ScoreKind::MateUpperbound => format!("mate {v} upperbound"),
+        })
+    }
+
+    fn update(&mut self, params: &[InfoParams]) {
+        // Only the principal variation is recorded with MultiPV.
+        if params
+            .iter()
+            .any(|p| matches!(*p, InfoParams::MultiPv(n) if n > 1))
+        {
+            return;
+        }
+
+        for param in params {
+            match *param {
+                InfoParams::Depth(depth, seldepth) => {
+                    self.depth = Some(depth);
+                    self.seldepth = seldepth;
+                }
+                InfoParams::Nodes(n) => self.nodes = Some(n as u64),
+                InfoParams::Nps(n) => self.nps = Some(n as u64),
+                InfoParams::HashFull(n) => self.hashfull = Some(n),
+                InfoParams::Score(v, ref kind) => self.score = Some((v, kind.clone())),
+                InfoParams::Pv(ref pv) => self.pv = pv.clone(),
+                _ => {}
+            }
+        }
+    }
 }
 
 pub struct UsiEngine {
     pub color: Color,
     pub name: String,
-    handler: UsiEngineHandler,
+    process: EngineProcess,
+    limit: SearchLimit,
+    movestogo: bool,
     think_state: Arc<RwLock<ThinkState>>,
 }
 
@@ -32,28 +99,92 @@ impl UsiEngine {
         color: Color,
         config: &EngineConfig,
         action_out: Sender<Action>,
-        mut read_hook: Option<ReadHookFn>,
+        read_hook: Option<ReadHookFn>,
+        write_hook: Option<WriteHookFn>,
+        stderr_hook: Option<StderrHookFn>,
         think_state: Arc<RwLock<ThinkState>>,
     ) -> Result<UsiEngine, Error> {
-        let mut handler = UsiEngineHandler::spawn(&config.engine_path, &config.working_dir)?;
-        handler.prepare()?;
+        let process = match stderr_hook {
+            Some(hook) => {
+                EngineProcess::spawn_with_stderr(&config.engine_path, &config.working_dir, hook)?
+            }
+            None => EngineProcess::spawn(&config.engine_path, &config.working_dir)?,
+        };
+        UsiEngine::with_process(
+            color,
+            config,
+            process,
+            action_out,
+            read_hook,
+            write_hook,
+            think_state,
+        )
+    }
 
-        let info = handler.get_info()?;
+    /// Creates a new instance with an engine process which has not been initialized yet.
+    ///
+    /// Commands sent before the game, e.g. the handshake and `setoption`, are reported to the hooks
+    /// as well as the commands during the game.
+    pub fn with_process(
+        color: Color,
+        config: &EngineConfig,
+        mut process: EngineProcess,
+        action_out: Sender<Action>,
+        mut read_hook: Option<ReadHookFn>,
+        mut write_hook: Option<WriteHookFn>,
+        think_state: Arc<RwLock<ThinkState>>,
+    ) -> Result<UsiEngine, Error> {
+        let info = EngineProfile::handshake_with(
+            &mut process,
+            |command, raw_str| {
+                if let Some(ref mut f) = write_hook {
+                    f(command, raw_str);
+                }
+            },
+            |output| read_hook.as_mut().map_or(Ok(()), |f| f(output)),
+        )?;
 
-        let mut options = info.options().clone();
+        // Buttons are excluded as sending them triggers actions instead of setting values.
+        let mut options = info
+            .options
+            .iter()
+            .filter(|opt| !matches!(opt.value, OptionKind::Button { .. }))
+            .map(|opt| {
+                (
+                    opt.name.to_string(),
+                    option_default(&opt.value).unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut set_option =
+            |name: &str, value: &str| match options.iter_mut().find(|(n, _)| n == name) {
+                Some(opt) => opt.1 = value.to_string(),
+                None => options.push((name.to_string(), value.to_string())),
+            };
         for (name, value) in &config.options {
-            options.insert(name.to_string(), value.to_string());
+            if let Some(opt) = info.options.iter().find(|opt| opt.name == *name) {
+                validate_option(&opt.value, value).map_err(|e| {
+                    Error::InvalidOption(format!(
+                        "invalid value \"{value}\" for the option \"{name}\" of {}: {e}",
+                        info.name
+                    ))
+                })?;
+            }
+            set_option(name, value);
         }
-        options.insert("USI_Ponder".to_string(), config.ponder.to_string());
+        set_option("USI_Ponder", &config.ponder.to_string());
 
         for (name, value) in &options {
-            handler.send_command(&GuiCommand::SetOption(
-                name.to_string(),
-                Some(value.to_string()),
-            ))?;
+            let command = GuiCommand::SetOption(name.to_string(), Some(value.to_string()));
+            if let Some(ref mut f) = write_hook {
+                f(&command, &command.to_string());
+            }
+            process.send(&command)?;
         }
 
-        handler.listen({
+        let close_out = action_out.clone();
+        let error_out = action_out.clone();
+        let on_output = {
             let ponder = config.ponder;
             let think_state = think_state.clone();
 
@@ -67,25 +198,25 @@ impl UsiEngine {
                         ponder_move,
                     ))) => {
                         if let Ok(mut think_state) = think_state.write() {
+                            let info = mem::take(&mut think_state.info);
                             if think_state.pending.is_some() {
+                                // The result of the stopped ponder search is discarded.
                                 action_out.send(Action::RequestState)?;
                             } else {
-                                if ponder {
-                                    if let Some(ref ponder_move) = *ponder_move {
-                                        if let Some(ponder_move) = Move::from_sfen(ponder_move) {
-                                            think_state.pondering = Some(ponder_move);
-                                        }
-                                    }
-                                }
+                                // Clears the previous ponder move if no ponder move is returned.
+                                think_state.pondering = ponder_move
+                                    .as_ref()
+                                    .filter(|_| ponder)
+                                    .and_then(|m| Move::from_sfen(m));
 
-                                if let Some(best_move) = Move::from_sfen(best_move_sfen) {
-                                    action_out.send(Action::MakeMove(
+                                match Move::from_sfen(best_move_sfen) {
+                                    Some(best_move) => action_out.send(Action::MakeMove(
                                         color,
                                         best_move,
                                         *output.timestamp(),
-                                    ))?;
-                                } else {
-                                    return Err(Error::Sfen(SfenError::IllegalMove));
+                                        info,
+                                    ))?,
+                                    None => return Err(Error::Sfen(SfenError::IllegalMove)),
                                 }
                             }
                         }
@@ -98,6 +229,7 @@ impl UsiEngine {
                     }
                     Some(EngineCommand::Info(v)) => {
                         if let Ok(mut think_state) = think_state.write() {
+                            think_state.info.update(v);
                             if let Some(InfoParams::Score(val, ScoreKind::CpExact)) = v
                                 .iter()
                                 .find(|item| matches!(*(*item), InfoParams::Score(_, _)))
@@ -109,36 +241,72 @@ impl UsiEngine {
                     _ => {}
                 }
 
-                if let Some(ref mut f) = read_hook {
-                    f(output)?;
-                }
-
                 Ok(())
             }
+        };
+        // Outputs which can't be handled, e.g. an unparsable move, lose the game instead of
+        // stopping the engine, so that the match goes on.
+        // Each output is reported before it is handled, so that it is reported before any commands
+        // sent in response to it.
+        let on_output = move |output: &EngineOutput| -> Result<(), Error> {
+            if let Some(ref mut f) = read_hook {
+                if let Err(e) = f(output) {
+                    error_out.send(Action::ReportFailed(e))?;
+                }
+            }
+            if on_output(output).is_err() {
+                error_out.send(Action::IllegalOutput(color))?;
+            }
+            Ok(())
+        };
+        process.listen(on_output, move || {
+            let _ = close_out.send(Action::Disconnected(color));
         })?;
         let engine = UsiEngine {
             color,
-            name: info.name().to_string(),
-            handler,
+            name: info.name,
+            process,
+            limit: config.limit,
+            movestogo: config.movestogo,
             think_state,
         };
 
         Ok(engine)
     }
 
+    /// Returns how often ponder moves were hit in the current game.
+    pub fn ponder_stats(&self) -> PonderStatistics {
+        self.think_state
+            .read()
+            .map(|think_state| think_state.ponder_stats)
+            .unwrap_or_default()
+    }
+
     pub fn notify_event(
         &mut self,
         event: &Event,
         hook: &mut Option<WriteHookFn>,
     ) -> Result<(), Error> {
+        let go_extra = match *event {
+            Event::NewTurn(ref game, _) | Event::NotifyState(ref game) => {
+                extra_go_params(&game.time, self.color, self.limit, self.movestogo)
+            }
+            _ => None,
+        };
         let mut write = {
-            let handler = &mut self.handler;
+            let process = &mut self.process;
 
             move |cmd: &GuiCommand| -> Result<(), Error> {
-                handler.send_command(cmd)?;
+                let raw_str = match (cmd, &go_extra) {
+                    (GuiCommand::Go(_), Some(extra)) => format!("{cmd} {extra}"),
+                    _ => cmd.to_string(),
+                };
+
+                // The hook is called first so that the command is reported before its response.
                 if let Some(ref mut f) = hook {
-                    f(cmd, &cmd.to_string());
+                    f(cmd, &raw_str);
                 }
+                process.send_raw(&raw_str)?;
                 Ok(())
             }
         };
@@ -149,19 +317,21 @@ impl UsiEngine {
             }
             Event::NewGame(_) => {
                 if let Ok(mut think_state) = self.think_state.write() {
-                    think_state.score = 0;
+                    *think_state = ThinkState::default();
                     write(&GuiCommand::UsiNewGame)?;
                 }
             }
             Event::NewTurn(game, _) => {
                 if let Ok(mut think_state) = self.think_state.write() {
                     if game.pos.side_to_move() == self.color {
-                        if let Some(ponder_move) = think_state.pondering {
+                        if let Some(ponder_move) = think_state.pondering.take() {
                             if let Some(last) = game.pos.move_history().last() {
                                 if *last == ponder_move {
                                     write(&GuiCommand::Ponderhit)?;
+                                    think_state.ponder_stats.record(true);
                                 } else {
                                     write(&GuiCommand::Stop)?;
+                                    think_state.ponder_stats.record(false);
 
                                     think_state.pending = Some(());
                                 }
@@ -171,11 +341,20 @@ impl UsiEngine {
 
                         let sfen = game.pos.to_sfen();
                         write(&GuiCommand::Position(sfen))?;
-                        write(&GuiCommand::Go(build_think_params(&game.time)))?;
+                        write(&GuiCommand::Go(build_think_params(
+                            &game.time, self.color, self.limit,
+                        )))?;
                     } else if let Some(ponder_move) = think_state.pondering {
+                        // The clock is sent as of the expected ponderhit, where the opponent has
+                        // played the ponder move instantly, as the engine keeps it after ponderhit.
+                        let mut time = game.time;
+                        time.consume(self.color.flip(), Duration::from_secs(0));
+
                         let sfen = game.pos.to_sfen();
                         write(&GuiCommand::Position(format!("{sfen} {ponder_move}")))?;
-                        write(&GuiCommand::Go(build_think_params(&game.time).ponder()))?;
+                        write(&GuiCommand::Go(
+                            build_think_params(&time, self.color, self.limit).ponder(),
+                        ))?;
                     }
                 }
             }
@@ -185,7 +364,9 @@ impl UsiEngine {
                         think_state.pending = None;
                         let sfen = game.pos.to_sfen();
                         write(&GuiCommand::Position(sfen))?;
-                        write(&GuiCommand::Go(build_think_params(&game.time)))?;
+                        write(&GuiCommand::Go(build_think_params(
+                            &game.time, self.color, self.limit,
+                        )))?;
                     }
                 }
             }
@@ -203,16 +384,30 @@ impl UsiEngine {
     }
 }
 
-fn build_think_params(time: &TimeControl) -> ThinkParams {
+fn build_think_params(time: &TimeControl, color: Color, limit: SearchLimit) -> ThinkParams {
+    // The clock is not sent with fixed search limits so that engines do not stop by time.
+    if limit != SearchLimit::Clock {
+        return ThinkParams::new();
+    }
+
     match *time {
         TimeControl::Byoyomi {
             black_time,
             white_time,
-            byoyomi,
-        } => ThinkParams::new()
-            .btime(black_time)
-            .wtime(white_time)
-            .byoyomi(byoyomi),
+            black_byoyomi,
+            white_byoyomi,
+        } => {
+            // Byoyomi of the engine itself is sent as USI has only one byoyomi parameter.
+            let byoyomi = if color == Color::Black {
+                black_byoyomi
+            } else {
+                white_byoyomi
+            };
+            ThinkParams::new()
+                .btime(black_time)
+                .wtime(white_time)
+                .byoyomi(byoyomi)
+        }
         TimeControl::FischerClock {
             black_time,
             white_time,
@@ -223,5 +418,26 @@ fn build_think_params(time: &TimeControl) -> ThinkParams {
             .wtime(white_time)
             .binc(black_inc)
             .winc(white_inc),
+        TimeControl::MovesPerPeriod {
+            black_time,
+            white_time,
+            ..
+        } => ThinkParams::new().btime(black_time).wtime(white_time),
     }
 }
+
+/// Returns parameters of `go` command which are not supported by `ThinkParams`.
+fn extra_go_params(
+    time: &TimeControl,
+    color: Color,
+    limit: SearchLimit,
+    movestogo: bool,
+) -> Option<String> {
+    if limit != SearchLimit::Clock {
+        return Some(limit.to_string());
+    }
+
+    time.moves_to_go(color)
+        .filter(|_| movestogo)
+        .map(|n| format!("movestogo {n}"))
+}
@@ -1,25 +1,30 @@
 use shogi::*;
+use std::cell::Cell;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use crate::config::MatchConfig;
-use crate::engine::{UsiEngine, WriteHookFn};
+use crate::config::{ClockMode, MatchConfig, SearchLimit};
+use crate::engine::{SearchInfo, UsiEngine, WriteHookFn};
 use crate::error::Error;
-use crate::game::{Game, GameOverReason, GameResult};
+use crate::game::{Game, GameOverReason, GameResult, MoveLog};
 use crate::reporter::Reporter;
 use crate::stats::MatchStatistics;
 
-const DEFAULT_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - \
-                                    1";
+pub const DEFAULT_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
 
 #[derive(Debug)]
 pub enum Action {
     Ready(Color),
     RequestState,
-    MakeMove(Color, Move, Instant),
+    MakeMove(Color, Move, Instant, SearchInfo),
     DeclareWinning(Color),
     Resign(Color),
+    /// The engine returned an output which can't be handled, e.g. an unparsable move.
+    IllegalOutput(Color),
+    Disconnected(Color),
+    /// Reporting an output of the engine failed, which is not the fault of the engine.
+    ReportFailed(Error),
 }
 
 #[derive(Debug)]
@@ -35,6 +40,8 @@ pub struct Environment {
     tx: Sender<Action>,
     rx: Receiver<Action>,
     max_ply: Option<u16>,
+    time_margin: Duration,
+    clock_mode: ClockMode,
 }
 
 impl Environment {
@@ -45,6 +52,8 @@ impl Environment {
             tx,
             rx,
             max_ply: None,
+            time_margin: Duration::from_secs(0),
+            clock_mode: ClockMode::Exact,
         }
     }
 
@@ -53,6 +62,16 @@ impl Environment {
         self
     }
 
+    pub fn time_margin(mut self, margin: Duration) -> Environment {
+        self.time_margin = margin;
+        self
+    }
+
+    pub fn clock_mode(mut self, mode: ClockMode) -> Environment {
+        self.clock_mode = mode;
+        self
+    }
+
     pub fn new_sender(&self) -> Sender<Action> {
         self.tx.clone()
     }
@@ -65,18 +84,26 @@ impl Environment {
         white_engine: &mut UsiEngine,
         reporter: Arc<Mutex<dyn Reporter + Send>>,
     ) -> Result<GameResult, Error> {
-        let mut game = Game::new(config.time.to_time_control());
+        let mut game = Game::new(config.time_control()?);
         game.black_player = black_engine.name.to_string();
         game.white_player = white_engine.name.to_string();
+        game.black_limit = config.black_engine.limit;
+        game.white_limit = config.white_engine.limit;
         game.pos
             .set_sfen(config.initial_pos.as_ref().map_or(DEFAULT_SFEN, |v| v))?;
 
         let mut black_write_hook = Some(create_write_hook(Color::Black, reporter.clone()));
-        let mut white_write_hook = Some(create_write_hook(Color::Black, reporter.clone()));
+        let mut white_write_hook = Some(create_write_hook(Color::White, reporter.clone()));
 
+        // Commands can't be sent to the engine which has been disconnected.
+        let disconnected = Cell::new(None);
         let mut transmit = |event: &Event| -> Result<(), Error> {
-            black_engine.notify_event(event, &mut black_write_hook)?;
-            white_engine.notify_event(event, &mut white_write_hook)?;
+            if disconnected.get() != Some(Color::Black) {
+                black_engine.notify_event(event, &mut black_write_hook)?;
+            }
+            if disconnected.get() != Some(Color::White) {
+                white_engine.notify_event(event, &mut white_write_hook)?;
+            }
 
             if let Ok(mut reporter) = reporter.lock() {
                 reporter.on_game_event(event, stats);
@@ -92,41 +119,98 @@ impl Environment {
         transmit(&Event::NewTurn(&mut game, Duration::from_secs(0)))?;
 
         let mut result: Option<GameResult> = None;
+        let mut overruns = Vec::new();
         while let Ok(action) = self.rx.recv() {
             match action {
                 Action::RequestState => {
+                    // The engine stopped pondering after a ponder miss and starts searching again.
+                    // The turn started when the opponent moved, so the time waiting for the engine
+                    // to stop is charged as well.
                     transmit(&Event::NotifyState(&mut game))?;
                 }
-                Action::MakeMove(c, ref m, ref ts) => {
+                Action::MakeMove(c, ref m, ref ts, ref info) => {
                     if c != game.pos.side_to_move() {
-                        result = Some(GameResult::new(Some(c), GameOverReason::IllegalMove));
+                        result = Some(GameResult::new(Some(c.flip()), GameOverReason::IllegalMove));
                         break;
                     }
 
+                    // The clock is charged in the way of the clock mode, while the measured time
+                    // is reported and recorded.
                     let elapsed = ts.duration_since(game.turn_start_time);
-                    if !game.time.consume(c, elapsed) {
-                        result = Some(GameResult::new(Some(c.flip()), GameOverReason::OutOfTime));
-                        break;
-                    }
-
-                    match game.pos.make_move(*m) {
-                        Ok(_) => {
-                            if let Some(max_ply) = self.max_ply {
-                                if game.pos.ply() >= max_ply {
-                                    result = Some(GameResult::new(None, GameOverReason::MaxPly));
-                                    break;
-                                }
+                    let mut charged = self.clock_mode.charge(elapsed);
+                    let allotted = game.time.allotted_time(c);
+                    if charged > allotted {
+                        // Time forfeits are disabled for engines searching with fixed limits.
+                        if game.limit(c) == SearchLimit::Clock {
+                            let overrun = charged - allotted;
+                            overruns.push((c, overrun));
+                            if overrun > self.time_margin {
+                                game.time.expire(c);
+                                result = Some(GameResult::new(
+                                    Some(c.flip()),
+                                    GameOverReason::OutOfTime,
+                                ));
+                                break;
                             }
+                        }
+                        // Overruns within the margin, and any overruns with fixed limits, are
+                        // clamped to the allotted time so that the clock runs out instead of
+                        // being left as is.
+                        charged = allotted;
+                    }
+                    let in_time = game.time.consume(c, charged);
+                    debug_assert!(in_time, "the charged time exceeds the allotted time");
 
-                            game.turn_start_time = Instant::now();
-                            transmit(&Event::NewTurn(&mut game, elapsed))?;
+                    let ply = game.pos.ply();
+                    let played = move_record(&game.pos, *m);
+                    let mut end = match game.pos.make_move(*m) {
+                        Ok(_) => None,
+                        // The board is updated by the move when a repetition is detected, but the
+                        // move is not kept in the move history.
+                        Err(MoveError::Repetition) => {
+                            Some(GameResult::new(None, GameOverReason::Repetition))
+                        }
+                        Err(MoveError::PerpetualCheckLose) => Some(GameResult::new(
+                            Some(c.flip()),
+                            GameOverReason::PerpetualCheck,
+                        )),
+                        Err(MoveError::PerpetualCheckWin) => {
+                            Some(GameResult::new(Some(c), GameOverReason::PerpetualCheck))
                         }
                         Err(_) => {
                             result =
                                 Some(GameResult::new(Some(c.flip()), GameOverReason::IllegalMove));
                             break;
                         }
+                    };
+                    if end.is_some() {
+                        game.final_move = played;
+                    }
+
+                    game.moves.push(MoveLog {
+                        color: c,
+                        ply,
+                        elapsed,
+                        info: info.clone(),
+                    });
+
+                    if let Some(max_ply) = self.max_ply {
+                        if end.is_none() && game.pos.ply() >= max_ply {
+                            end = Some(GameResult::new(None, GameOverReason::MaxPly));
+                        }
+                    }
+
+                    if end.is_some() {
+                        // The final move is recorded, but not sent to the engines as the game is over.
+                        if let Ok(mut reporter) = reporter.lock() {
+                            reporter.on_game_event(&Event::NewTurn(&mut game, elapsed), stats);
+                        }
+                        result = end;
+                        break;
                     }
+
+                    game.turn_start_time = Instant::now();
+                    transmit(&Event::NewTurn(&mut game, elapsed))?;
                 }
                 Action::Resign(c) => {
                     if c != game.pos.side_to_move() {
@@ -146,16 +230,35 @@ impl Environment {
                             GameOverReason::DeclareWinning,
                         ));
                     }
+                    break;
+                }
+                Action::IllegalOutput(c) => {
+                    result = Some(GameResult::new(Some(c.flip()), GameOverReason::IllegalMove));
+                    break;
+                }
+                Action::ReportFailed(e) => return Err(e),
+                Action::Disconnected(c) => {
+                    disconnected.set(Some(c));
+                    result = Some(GameResult::new(
+                        Some(c.flip()),
+                        GameOverReason::Disconnected,
+                    ));
+                    break;
                 }
                 _ => {}
             }
         }
 
-        if let Some(result) = &result {
-            transmit(&Event::GameOver(result.winner, result.reason))?;
-        }
+        let mut result = result.ok_or(Error::EngineNotResponded)?;
+        result.overruns = overruns;
+        transmit(&Event::GameOver(result.winner, result.reason))?;
+        result.black_ponder = black_engine.ponder_stats();
+        result.white_ponder = white_engine.ponder_stats();
+        result.black_time = game.time.black_time();
+        result.white_time = game.time.white_time();
+        result.moves = game.moves;
 
-        Ok(result.unwrap())
+        Ok(result)
     }
 
     fn wait_readyok(&self) -> Result<(), Error> {
@@ -163,16 +266,21 @@ impl Environment {
 
         // Currently no timeout value is set for waiting "readyok" command.
         while let Ok(action) = self.rx.recv() {
-            if let Action::Ready(c) = action {
-                if c == Color::Black {
-                    state.0 = true;
-                } else {
-                    state.1 = true
-                }
+            match action {
+                Action::Ready(c) => {
+                    if c == Color::Black {
+                        state.0 = true;
+                    } else {
+                        state.1 = true
+                    }
 
-                if state.0 && state.1 {
-                    return Ok(());
+                    if state.0 && state.1 {
+                        return Ok(());
+                    }
                 }
+                Action::Disconnected(c) => return Err(Error::EngineDisconnected(c)),
+                Action::ReportFailed(e) => return Err(e),
+                _ => {}
             }
         }
 
@@ -180,7 +288,30 @@ impl Environment {
     }
 }
 
-fn create_write_hook(color: Color, reporter: Arc<Mutex<dyn Reporter + Send>>) -> WriteHookFn {
+/// Returns the record of the move, which must be called before the move is made.
+pub fn move_record(pos: &Position, m: Move) -> Option<MoveRecord> {
+    match m {
+        Move::Normal { from, to, promote } => {
+            let moved = (*pos.piece_at(from))?;
+            Some(MoveRecord::Normal {
+                from,
+                to,
+                placed: if promote { moved.promote()? } else { moved },
+                captured: *pos.piece_at(to),
+                promoted: promote,
+            })
+        }
+        Move::Drop { to, piece_type } => Some(MoveRecord::Drop {
+            to,
+            piece: Piece {
+                piece_type,
+                color: pos.side_to_move(),
+            },
+        }),
+    }
+}
+
+pub fn create_write_hook(color: Color, reporter: Arc<Mutex<dyn Reporter + Send>>) -> WriteHookFn {
     let write_reporter = reporter.clone();
 
     Box::new(move |command, raw_str| {
@@ -190,3 +321,579 @@ fn create_write_hook(color: Color, reporter: Arc<Mutex<dyn Reporter + Send>>) ->
             .on_send_command(color, command, raw_str);
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{ReadHookFn, ThinkState};
+    use crate::mock::{config, MockEngine, Reply};
+    use crate::stats::GamePhase;
+    use shogi::bitboard::Factory;
+    use std::io;
+    use std::sync::RwLock;
+    use usi::{EngineCommand, ScoreKind};
+
+    // Black has 10 pieces and 28 points in the enemy camp, satisfying the entering king rule.
+    const DECLARE_WIN_SFEN: &str = "4K4/RB5BR/1PPPPPP2/9/9/9/9/9/4k4 b 2G 1";
+
+    #[derive(Default)]
+    struct RecordingReporter {
+        events: Vec<String>,
+    }
+
+    impl Reporter for RecordingReporter {
+        fn on_game_event(&mut self, event: &Event, _: &MatchStatistics) {
+            let name = match *event {
+                Event::IsReady => "isready".to_string(),
+                Event::NewGame(_) => "newgame".to_string(),
+                Event::NewTurn(ref game, _) => format!("turn {}", game.pos.ply()),
+                Event::NotifyState(_) => "state".to_string(),
+                Event::GameOver(..) => "gameover".to_string(),
+            };
+            self.events.push(name);
+        }
+    }
+
+    fn play(
+        config: &MatchConfig,
+        black: MockEngine,
+        white: MockEngine,
+    ) -> (Result<GameResult, Error>, Vec<String>) {
+        play_with_hook(config, black, white, None)
+    }
+
+    fn play_with_hook(
+        config: &MatchConfig,
+        black: MockEngine,
+        white: MockEngine,
+        white_hook: Option<ReadHookFn>,
+    ) -> (Result<GameResult, Error>, Vec<String>) {
+        Factory::init();
+
+        let mut env = Environment::new()
+            .max_ply(config.max_ply)
+            .time_margin(config.time_margin)
+            .clock_mode(config.clock_mode);
+        let stats = MatchStatistics::new(1);
+        let reporter = Arc::new(Mutex::new(RecordingReporter::default()));
+
+        let mut black_engine = UsiEngine::with_process(
+            Color::Black,
+            &config.black_engine,
+            black.spawn(),
+            env.new_sender(),
+            None,
+            None,
+            Arc::new(RwLock::new(ThinkState::default())),
+        )
+        .unwrap();
+        let mut white_engine = UsiEngine::with_process(
+            Color::White,
+            &config.white_engine,
+            white.spawn(),
+            env.new_sender(),
+            white_hook,
+            None,
+            Arc::new(RwLock::new(ThinkState::default())),
+        )
+        .unwrap();
+
+        let result = env.start_game(
+            config,
+            &stats,
+            &mut black_engine,
+            &mut white_engine,
+            reporter.clone(),
+        );
+        let events = reporter.lock().unwrap().events.clone();
+        (result, events)
+    }
+
+    fn assert_result(result: &GameResult, winner: Option<Color>, reason: GameOverReason) {
+        assert_eq!(winner, result.winner);
+        assert_eq!(format!("{reason:?}"), format!("{:?}", result.reason));
+    }
+
+    #[test]
+    fn resign() {
+        let (result, events) = play(
+            &config(),
+            MockEngine::new("b").then(Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Resign),
+        );
+
+        assert_result(&result.unwrap(), Some(Color::Black), GameOverReason::Resign);
+        assert_eq!(
+            vec!["isready", "newgame", "turn 1", "turn 2", "gameover"],
+            events
+        );
+    }
+
+    #[test]
+    fn time_statistics() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b")
+                .then_after(Duration::from_millis(100), Reply::Move("7g7f"))
+                .then_after(Duration::from_millis(300), Reply::Move("2g2f")),
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+        );
+
+        let result = result.unwrap();
+        let plies = result.moves.iter().map(|m| m.ply).collect::<Vec<_>>();
+        assert_eq!(vec![1, 2, 3], plies);
+        assert_eq!(Color::White, result.moves[1].color);
+
+        let mut stats = MatchStatistics::new(1);
+        stats.record_game(&result);
+        let black = stats.time(Color::Black);
+        assert_eq!(2, black.moves());
+        assert!(black.median() >= Duration::from_millis(200));
+        assert!(black.phase_average(GamePhase::Opening) >= Duration::from_millis(200));
+        assert_eq!(
+            Duration::from_secs(0),
+            black.phase_average(GamePhase::Endgame)
+        );
+        assert_eq!(0, black.time_losses());
+    }
+
+    #[test]
+    fn csa_clock_mode() {
+        let mut config = config();
+        config.time.black_time = Duration::from_secs(10);
+        config.clock_mode = ClockMode::Csa;
+
+        let (result, _) = play(
+            &config,
+            MockEngine::new("b").then_after(Duration::from_millis(100), Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Resign),
+        );
+
+        // The clock is charged a whole second, while the measured time is recorded.
+        let result = result.unwrap();
+        assert_eq!(Duration::from_secs(9), result.black_time);
+        assert!(result.moves[0].elapsed >= Duration::from_millis(100));
+        assert!(result.moves[0].elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn search_info() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b")
+                .then(Reply::Move("7g7f"))
+                .info("depth 10 seldepth 15 nodes 100000 nps 200000 score cp 50 pv 7g7f 3c3d")
+                .info("depth 12 seldepth 18 multipv 2 score cp 20 pv 2g2f")
+                .info("nodes 150000 nps 300000 hashfull 500")
+                .then(Reply::Move("2g2f"))
+                .info("depth 8 nps 100000"),
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+        );
+
+        let result = result.unwrap();
+        let info = &result.moves[0].info;
+        assert_eq!(Some(10), info.depth);
+        assert_eq!(Some(15), info.seldepth);
+        assert_eq!(Some(150000), info.nodes);
+        assert_eq!(Some(300000), info.nps);
+        assert_eq!(Some(500), info.hashfull);
+        assert_eq!(Some((50, ScoreKind::CpExact)), info.score);
+        assert_eq!(vec!["7g7f", "3c3d"], info.pv);
+        assert_eq!(None, result.moves[1].info.depth);
+
+        let mut stats = MatchStatistics::new(1);
+        stats.record_game(&result);
+        let search = stats.search(Color::Black);
+        assert_eq!(Some(9.0), search.average_depth());
+        assert_eq!(Some(200000), search.average_nps());
+    }
+
+    #[test]
+    fn illegal_move() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b").then(Reply::Move("7g7e")),
+            MockEngine::new("w"),
+        );
+
+        assert_result(
+            &result.unwrap(),
+            Some(Color::White),
+            GameOverReason::IllegalMove,
+        );
+    }
+
+    #[test]
+    fn out_of_time() {
+        let mut config = config();
+        config.time.black_time = Duration::from_millis(100);
+        config.time.black_byoyomi = Some(Duration::from_millis(50));
+
+        let (result, _) = play(
+            &config,
+            MockEngine::new("b").then_after(Duration::from_millis(300), Reply::Move("7g7f")),
+            MockEngine::new("w"),
+        );
+
+        let result = result.unwrap();
+        assert_result(&result, Some(Color::White), GameOverReason::OutOfTime);
+        // The remaining time of the loser runs out.
+        assert_eq!(Duration::from_secs(0), result.black_time);
+    }
+
+    #[test]
+    fn unparsable_move() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b").then(Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Move("xyz")),
+        );
+
+        assert_result(
+            &result.unwrap(),
+            Some(Color::Black),
+            GameOverReason::IllegalMove,
+        );
+    }
+
+    #[test]
+    fn read_hook_error() {
+        let hook: ReadHookFn = Box::new(|output| match output.response() {
+            Some(EngineCommand::BestMove(_)) => Err(Error::Io(io::Error::other("disk full"))),
+            _ => Ok(()),
+        });
+        let (result, _) = play_with_hook(
+            &config(),
+            MockEngine::new("b").then(Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+            Some(hook),
+        );
+
+        // The game is aborted instead of being lost by the engine.
+        assert!(matches!(result, Err(Error::Io(_))), "{result:?}");
+    }
+
+    #[test]
+    fn time_margin() {
+        let mut config = config();
+        config.time.black_byoyomi = Some(Duration::from_millis(50));
+        config.time_margin = Duration::from_millis(1000);
+
+        let (result, _) = play(
+            &config,
+            MockEngine::new("b").then_after(Duration::from_millis(300), Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Resign),
+        );
+
+        let result = result.unwrap();
+        assert_result(&result, Some(Color::Black), GameOverReason::Resign);
+        assert_eq!(1, result.overruns.len());
+        assert_eq!(Color::Black, result.overruns[0].0);
+        assert!(result.overruns[0].1 >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn moves_per_period() {
+        let mut config = config();
+        config.time.black_time = Duration::from_secs(60);
+        config.time.white_time = Duration::from_secs(60);
+        config.time.black_byoyomi = None;
+        config.time.white_byoyomi = None;
+        config.time.period_moves = Some(2);
+        config.time.period_time = Some(Duration::from_secs(60));
+        config.black_engine.movestogo = true;
+
+        let black = MockEngine::new("b")
+            .then(Reply::Move("7g7f"))
+            .then(Reply::Move("2g2f"));
+        let black_log = black.log();
+        let (result, _) = play(
+            &config,
+            black,
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+        );
+
+        assert_result(&result.unwrap(), Some(Color::Black), GameOverReason::Resign);
+        let log = black_log.lock().unwrap();
+        let go = log
+            .iter()
+            .filter(|cmd| cmd.starts_with("go"))
+            .collect::<Vec<_>>();
+        assert!(go[0].ends_with("movestogo 2"), "{}", go[0]);
+        assert!(go[1].ends_with("movestogo 1"), "{}", go[1]);
+    }
+
+    #[test]
+    fn max_ply() {
+        let mut config = config();
+        config.max_ply = Some(2);
+
+        let (result, events) = play(
+            &config,
+            MockEngine::new("b").then(Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+        );
+
+        let result = result.unwrap();
+        assert_result(&result, None, GameOverReason::MaxPly);
+        assert_eq!(1, result.moves.len());
+        assert_eq!(
+            vec!["turn 1", "turn 2", "gameover"],
+            events[events.len() - 3..]
+        );
+    }
+
+    #[test]
+    fn repetition() {
+        let mut black = MockEngine::new("b");
+        let mut white = MockEngine::new("w");
+        for _ in 0..3 {
+            black = black.then(Reply::Move("5i4h")).then(Reply::Move("4h5i"));
+            white = white.then(Reply::Move("5a4b")).then(Reply::Move("4b5a"));
+        }
+
+        let (result, events) = play(&config(), black, white);
+
+        let result = result.unwrap();
+        assert_result(&result, None, GameOverReason::Repetition);
+        // The move causing the repetition is recorded as well.
+        assert_eq!(12, result.moves.len());
+        assert_eq!(Color::White, result.moves[11].color);
+        assert_eq!(
+            vec!["turn 12", "turn 13", "gameover"],
+            events[events.len() - 3..]
+        );
+    }
+
+    #[test]
+    fn perpetual_check() {
+        let mut config = config();
+        config.initial_pos = Some("4k4/9/9/9/9/9/9/9/4K3R b - 1".to_string());
+
+        // Black keeps checking with the rook while White moves the king back and forth.
+        let mut black = MockEngine::new("b").then(Reply::Move("1i1a"));
+        let mut white = MockEngine::new("w");
+        for _ in 0..3 {
+            black = black.then(Reply::Move("1a1b")).then(Reply::Move("1b1a"));
+            white = white.then(Reply::Move("5a5b")).then(Reply::Move("5b5a"));
+        }
+
+        let (result, _) = play(&config, black, white);
+
+        let result = result.unwrap();
+        assert_result(&result, Some(Color::White), GameOverReason::PerpetualCheck);
+        assert_eq!(13, result.moves.len());
+        assert_eq!(Color::Black, result.moves[12].color);
+    }
+
+    #[test]
+    fn out_of_turn_move() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b")
+                .then(Reply::Move("7g7f"))
+                .unsolicited("bestmove 2g2f"),
+            MockEngine::new("w").then_after(Duration::from_secs(1), Reply::Move("3c3d")),
+        );
+
+        assert_result(
+            &result.unwrap(),
+            Some(Color::White),
+            GameOverReason::IllegalMove,
+        );
+    }
+
+    #[test]
+    fn declare_winning() {
+        let mut config = config();
+        config.initial_pos = Some(DECLARE_WIN_SFEN.to_string());
+
+        let (result, _) = play(
+            &config,
+            MockEngine::new("b").then(Reply::Win),
+            MockEngine::new("w"),
+        );
+
+        assert_result(
+            &result.unwrap(),
+            Some(Color::Black),
+            GameOverReason::DeclareWinning,
+        );
+    }
+
+    #[test]
+    fn declare_winning_illegally() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b").then(Reply::Win),
+            MockEngine::new("w"),
+        );
+
+        assert_result(
+            &result.unwrap(),
+            Some(Color::White),
+            GameOverReason::DeclareWinning,
+        );
+    }
+
+    #[test]
+    fn invalid_option() {
+        Factory::init();
+
+        let mut config = config();
+        config
+            .black_engine
+            .options
+            .push(("USI_Hash".to_string(), "0.5".to_string()));
+        let env = Environment::new();
+
+        let engine = UsiEngine::with_process(
+            Color::Black,
+            &config.black_engine,
+            MockEngine::new("b").spawn(),
+            env.new_sender(),
+            None,
+            None,
+            Arc::new(RwLock::new(ThinkState::default())),
+        );
+        assert!(matches!(engine, Err(Error::InvalidOption(_))));
+    }
+
+    #[test]
+    fn crash() {
+        let (result, _) = play(
+            &config(),
+            MockEngine::new("b").then(Reply::Move("7g7f")),
+            MockEngine::new("w").then(Reply::Crash),
+        );
+
+        assert_result(
+            &result.unwrap(),
+            Some(Color::Black),
+            GameOverReason::Disconnected,
+        );
+    }
+
+    #[test]
+    fn fixed_search_limit() {
+        let mut config = config();
+        config.time.black_time = Duration::from_millis(100);
+        config.time.black_byoyomi = Some(Duration::from_millis(50));
+        config.black_engine.limit = SearchLimit::Depth(5);
+
+        let black = MockEngine::new("b")
+            .then_after(Duration::from_millis(300), Reply::Move("7g7f"))
+            .then(Reply::Resign);
+        let black_log = black.log();
+        let (result, _) = play(
+            &config,
+            black,
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+        );
+
+        // Exceeding the clock does not lose the game with a fixed limit, but uses up the clock.
+        let result = result.unwrap();
+        assert_result(&result, Some(Color::White), GameOverReason::Resign);
+        assert_eq!(Duration::from_secs(0), result.black_time);
+        assert!(black_log
+            .lock()
+            .unwrap()
+            .contains(&"go depth 5".to_string()));
+    }
+
+    #[test]
+    fn ponder_hit() {
+        let mut config = config();
+        config.black_engine.ponder = true;
+
+        let black = MockEngine::new("b")
+            .then(Reply::MoveWithPonder("7g7f", "3c3d"))
+            .then(Reply::Move("2g2f"));
+        let black_log = black.log();
+        let (result, _) = play(
+            &config,
+            black,
+            MockEngine::new("w")
+                .then(Reply::Move("3c3d"))
+                .then(Reply::Move("8c8d")),
+        );
+
+        let result = result.unwrap();
+        assert_result(&result, Some(Color::White), GameOverReason::Resign);
+        assert_eq!(1, result.black_ponder.hits());
+        assert_eq!(0, result.black_ponder.misses());
+
+        let log = black_log.lock().unwrap();
+        assert!(log.contains(&"setoption name USI_Ponder value true".to_string()));
+        let ponder_pos = log.iter().position(|c| c.ends_with("moves 7g7f 3c3d"));
+        let ponderhit = log.iter().position(|c| c == "ponderhit");
+        assert!(ponder_pos.is_some() && ponderhit.is_some());
+        assert!(log[ponder_pos.unwrap() + 1].starts_with("go ponder"));
+        assert!(ponder_pos < ponderhit);
+
+        // The ponder move must not be reused after a bestmove without a ponder move.
+        let go_ponder = log.iter().filter(|c| c.starts_with("go ponder")).count();
+        assert_eq!(1, go_ponder);
+    }
+
+    #[test]
+    fn ponder_miss() {
+        let mut config = config();
+        config.black_engine.ponder = true;
+
+        let black = MockEngine::new("b")
+            .then(Reply::MoveWithPonder("7g7f", "8c8d"))
+            .then(Reply::Resign);
+        let black_log = black.log();
+        let (result, events) = play(
+            &config,
+            black,
+            MockEngine::new("w").then(Reply::Move("3c3d")),
+        );
+
+        assert_result(&result.unwrap(), Some(Color::White), GameOverReason::Resign);
+        assert!(events.contains(&"state".to_string()));
+
+        let log = black_log.lock().unwrap();
+        let stop = log.iter().position(|c| c == "stop").unwrap();
+        assert!(log[stop + 1].ends_with("moves 7g7f 3c3d"));
+        assert!(log[stop + 2].starts_with("go btime"));
+        assert!(!log.contains(&"ponderhit".to_string()));
+    }
+
+    #[test]
+    fn ponder_miss_with_slow_stop() {
+        let mut config = config();
+        config.black_engine.ponder = true;
+        config.time.black_byoyomi = Some(Duration::from_millis(300));
+
+        // The time waiting for the engine to stop pondering is charged, which exceeds byoyomi.
+        let black = MockEngine::new("b")
+            .then(Reply::MoveWithPonder("7g7f", "8c8d"))
+            .then(Reply::Move("2g2f"))
+            .stop_after(Duration::from_millis(600));
+        let black_log = black.log();
+        let (result, events) = play(
+            &config,
+            black,
+            MockEngine::new("w")
+                .then(Reply::Move("3c3d"))
+                .then(Reply::Resign),
+        );
+
+        let result = result.unwrap();
+        assert_result(&result, Some(Color::White), GameOverReason::OutOfTime);
+        assert_eq!(0, result.black_ponder.hits());
+        assert_eq!(1, result.black_ponder.misses());
+        assert_eq!(
+            vec!["isready", "newgame", "turn 1", "turn 2", "turn 3", "state", "gameover"],
+            events
+        );
+
+        // The discarded bestmove of the ponder search must not be played.
+        let log = black_log.lock().unwrap();
+        let stop = log.iter().position(|c| c == "stop").unwrap();
+        assert!(log[stop + 1].ends_with("moves 7g7f 3c3d"));
+        assert!(log[stop + 2].starts_with("go btime"));
+    }
+}
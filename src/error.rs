@@ -1,4 +1,4 @@
-use shogi::{MoveError, SfenError};
+use shogi::{Color, MoveError, SfenError};
 use std::sync::mpsc::{RecvError, SendError};
 use std::{error, fmt, io};
 
@@ -9,7 +9,11 @@ pub enum Error {
     Move(MoveError),
     Io(io::Error),
     Channel(Box<dyn error::Error + Send + Sync>),
+    /// An option value which does not match the type declared by the engine.
+    InvalidOption(String),
     EngineNotResponded,
+    EngineDisconnected(Color),
+    Timeout,
 }
 
 impl fmt::Display for Error {
@@ -20,7 +24,13 @@ impl fmt::Display for Error {
             Error::Move(ref e) => write!(f, "{e}"),
             Error::Io(ref e) => write!(f, "{e}"),
             Error::Channel(ref e) => write!(f, "{e}"),
+            Error::InvalidOption(ref e) => write!(f, "{e}"),
             Error::EngineNotResponded => write!(f, "the engine did not return 'readyok' command"),
+            Error::EngineDisconnected(c) => {
+                let name = if c == Color::Black { "black" } else { "white" };
+                write!(f, "the {name} engine terminated unexpectedly")
+            }
+            Error::Timeout => write!(f, "the engine did not respond in time"),
         }
     }
 }
@@ -33,7 +43,10 @@ impl error::Error for Error {
             Error::Move(ref e) => Some(e),
             Error::Io(ref e) => Some(e),
             Error::Channel(_) => None,
+            Error::InvalidOption(_) => None,
             Error::EngineNotResponded => None,
+            Error::EngineDisconnected(_) => None,
+            Error::Timeout => None,
         }
     }
 }
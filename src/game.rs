@@ -1,43 +1,125 @@
-use shogi::{Color, Position, TimeControl};
-use std::time::Instant;
+use shogi::{Color, MoveRecord, Position};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::clock::TimeControl;
+use crate::config::SearchLimit;
+use crate::engine::SearchInfo;
+use crate::stats::PonderStatistics;
 
 #[derive(Debug)]
 pub struct Game {
     pub black_player: String,
     pub white_player: String,
+    pub black_limit: SearchLimit,
+    pub white_limit: SearchLimit,
     pub pos: Position,
     pub time: TimeControl,
     pub turn_start_time: Instant,
+    pub moves: Vec<MoveLog>,
+    /// The move which ended the game by repetition, which is not kept in the move history of `pos`.
+    pub final_move: Option<MoveRecord>,
+}
+
+/// Represents a move played in a game along with how it was played.
+#[derive(Debug, Clone)]
+pub struct MoveLog {
+    pub color: Color,
+    pub ply: u16,
+    /// The time spent on the move as measured, regardless of how the clock is charged.
+    pub elapsed: Duration,
+    /// The last search statistics reported before the move.
+    pub info: SearchInfo,
 }
 
 #[derive(Debug, Clone)]
 pub struct GameResult {
     pub winner: Option<Color>,
     pub reason: GameOverReason,
+    /// Moves which took longer than the allotted time, with the exceeded amount.
+    pub overruns: Vec<(Color, Duration)>,
+    pub black_ponder: PonderStatistics,
+    pub white_ponder: PonderStatistics,
+    pub moves: Vec<MoveLog>,
+    /// The remaining time on the clock of each player at the end of the game.
+    pub black_time: Duration,
+    pub white_time: Duration,
 }
 
 impl GameResult {
     pub fn new(winner: Option<Color>, reason: GameOverReason) -> GameResult {
-        GameResult { winner, reason }
+        GameResult {
+            winner,
+            reason,
+            overruns: Vec::new(),
+            black_ponder: PonderStatistics::default(),
+            white_ponder: PonderStatistics::default(),
+            moves: Vec::new(),
+            black_time: Duration::from_secs(0),
+            white_time: Duration::from_secs(0),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum GameOverReason {
     Resign,
     IllegalMove,
     OutOfTime,
     MaxPly,
     DeclareWinning,
+    /// The same position occurred four times.
+    Repetition,
+    /// The same position occurred four times by continuous checks.
+    PerpetualCheck,
+    /// The engine exited or closed its output during the game.
+    Disconnected,
+}
+
+impl fmt::Display for GameOverReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            GameOverReason::Resign => "resign",
+            GameOverReason::IllegalMove => "illegal_move",
+            GameOverReason::OutOfTime => "out_of_time",
+            GameOverReason::MaxPly => "max_ply",
+            GameOverReason::DeclareWinning => "declare_winning",
+            GameOverReason::Repetition => "repetition",
+            GameOverReason::PerpetualCheck => "perpetual_check",
+            GameOverReason::Disconnected => "disconnected",
+        };
+        write!(f, "{name}")
+    }
 }
+
 impl Game {
     pub fn new(initial_time: TimeControl) -> Game {
         Game {
             black_player: String::new(),
             white_player: String::new(),
+            black_limit: SearchLimit::Clock,
+            white_limit: SearchLimit::Clock,
             pos: Position::new(),
             time: initial_time,
             turn_start_time: Instant::now(),
+            moves: Vec::new(),
+            final_move: None,
+        }
+    }
+
+    /// Returns the last move played in the game, including the move ending the game by repetition.
+    pub fn last_move(&self) -> Option<&MoveRecord> {
+        self.final_move
+            .as_ref()
+            .or_else(|| self.pos.move_history().last())
+    }
+
+    /// Returns the search limit of the given player.
+    pub fn limit(&self, c: Color) -> SearchLimit {
+        if c == Color::Black {
+            self.black_limit
+        } else {
+            self.white_limit
         }
     }
 }
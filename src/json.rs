@@ -0,0 +1,155 @@
+use std::fmt;
+
+/// A minimal JSON value used for machine-readable outputs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    /// Written as null if the value is not finite, which JSON cannot represent.
+    Float(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn object() -> JsonValue {
+        JsonValue::Object(Vec::new())
+    }
+
+    /// Appends a field to the object. Does nothing if `self` is not an object.
+    #[must_use]
+    pub fn field<V: Into<JsonValue>>(mut self, key: &str, value: V) -> JsonValue {
+        if let JsonValue::Object(ref mut fields) = self {
+            fields.push((key.to_string(), value.into()));
+        }
+        self
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(b) => write!(f, "{b}"),
+            JsonValue::Integer(n) => write!(f, "{n}"),
+            JsonValue::Float(v) if v.is_finite() => write!(f, "{v}"),
+            JsonValue::Float(_) => write!(f, "null"),
+            JsonValue::String(ref s) => write_str(f, s),
+            JsonValue::Array(ref items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl From<bool> for JsonValue {
+    fn from(v: bool) -> JsonValue {
+        JsonValue::Bool(v)
+    }
+}
+
+impl From<i32> for JsonValue {
+    fn from(v: i32) -> JsonValue {
+        JsonValue::Integer(v.into())
+    }
+}
+
+impl From<i64> for JsonValue {
+    fn from(v: i64) -> JsonValue {
+        JsonValue::Integer(v)
+    }
+}
+
+impl From<f64> for JsonValue {
+    fn from(v: f64) -> JsonValue {
+        JsonValue::Float(v)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(v: &str) -> JsonValue {
+        JsonValue::String(v.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(v: String) -> JsonValue {
+        JsonValue::String(v)
+    }
+}
+
+impl<T: Into<JsonValue>> From<Option<T>> for JsonValue {
+    fn from(v: Option<T>) -> JsonValue {
+        v.map_or(JsonValue::Null, Into::into)
+    }
+}
+
+impl<T: Into<JsonValue>> From<Vec<T>> for JsonValue {
+    fn from(v: Vec<T>) -> JsonValue {
+        JsonValue::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_strings() {
+        let value = JsonValue::from("a\"b\\c\nd\re\tf\u{1}g\u{7f}将棋");
+        assert_eq!(
+            "\"a\\\"b\\\\c\\nd\\re\\tf\\u0001g\u{7f}将棋\"",
+            value.to_string()
+        );
+    }
+
+    #[test]
+    fn nested_values() {
+        let value = JsonValue::object()
+            .field("key \"quoted\"", vec![1, 2])
+            .field("none", Option::<i32>::None)
+            .field("nan", f64::NAN)
+            .field("half", 0.5)
+            .field("empty", JsonValue::object());
+        assert_eq!(
+            r#"{"key \"quoted\"":[1,2],"none":null,"nan":null,"half":0.5,"empty":{}}"#,
+            value.to_string()
+        );
+    }
+}
@@ -1,27 +1,53 @@
+mod check;
+mod clock;
 mod config;
 mod engine;
 mod environment;
 mod error;
 mod game;
+mod json;
+#[cfg(test)]
+mod mock;
+mod process;
+mod profile;
 mod reporter;
+mod results;
+mod scaffold;
 mod stats;
 
-use clap::{crate_version, Arg, Command};
+use clap::{crate_version, Arg, ArgAction, ArgMatches, Command};
 use shogi::bitboard::Factory;
 use shogi::Color;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 
 use crate::error::Error;
+use check::run_checks;
 use config::*;
 use engine::*;
 use environment::*;
-use reporter::{BoardReporter, CsaReporter, Reporter, SimpleReporter, UsiReporter};
+use game::GameOverReason;
+use profile::EngineProfile;
+use reporter::{
+    init_local_offset, BoardReporter, CsaReporter, JkfReporter, JsonlReporter, Ki2Reporter,
+    KifReporter, MultiReporter, RecordWriter, Reporter, SfenReporter, SimpleReporter,
+    UsiLogReporter, UsiReporter,
+};
+use results::match_results;
+use scaffold::{absolute_engine_path, default_working_dir, generate_config};
 use stats::*;
 
 fn main() {
+    Factory::init();
+    init_local_offset();
+
     let matches = Command::new("usirun")
         .version(crate_version!())
         .about("A command line utility for running games between USI compliant Shogi engines.")
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("config")
                 .short('c')
@@ -38,67 +64,357 @@ fn main() {
                 .value_name("MODE")
                 .help("Displays ")
                 .num_args(1)
-                .value_parser(["board", "csa", "command", "simple"])
+                .value_parser(["board", "csa", "kif", "ki2", "jkf", "jsonl", "sfen", "command", "simple"])
                 .default_value("simple"),
         )
+        .arg(
+            Arg::new("sfen_details")
+                .long("sfen-details")
+                .help("Appends the termination reason and the engine names to the results in sfen mode")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FORMAT:PATH")
+                .help(
+                    "Also writes game records in csa, jkf, jsonl, ki2, kif or sfen format, \
+                     to a file per game if the path ends with a separator, e.g. \"csa:games/\"",
+                )
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("log")
+                .long("log")
+                .value_name("FORMAT:PATH")
+                .help(
+                    "Also writes USI commands and stderr to logs per game and engine, \
+                     e.g. \"usi:logs/\"",
+                )
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("results_json")
+                .long("results-json")
+                .value_name("FILE")
+                .help("Writes the final results of the match to the file in JSON")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("tc")
+                .long("tc")
+                .value_name("TC")
+                .help(
+                    "Overrides the time control in seconds, e.g. \"60+1\" (Fischer), \
+                     \"300/10b\" (main time plus byoyomi) or \"10b\" (byoyomi only)",
+                )
+                .num_args(1),
+        )
+        .subcommand(
+            Command::new("engine-info")
+                .about("Prints the name, the author and available options of the engine")
+                .arg(
+                    Arg::new("engine")
+                        .short('e')
+                        .long("engine")
+                        .value_name("PATH")
+                        .help("The path to the USI engine executable")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("working_dir")
+                        .short('w')
+                        .long("working-dir")
+                        .value_name("DIR")
+                        .help("The working directory when launching the engine")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format")
+                        .num_args(1)
+                        .value_parser(["table", "json"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Generates a configuration file with options reported by the engines")
+                .arg(
+                    Arg::new("black")
+                        .short('b')
+                        .long("black")
+                        .value_name("PATH")
+                        .help("The path to the USI engine executable for the black player")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("white")
+                        .short('w')
+                        .long("white")
+                        .value_name("PATH")
+                        .help("The path to the USI engine executable for the white player")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("TOML")
+                        .help("Writes the configuration to the file instead of stdout")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about(
+                    "Checks the engine's conformance to the USI protocol with scripted scenarios",
+                )
+                .arg(
+                    Arg::new("engine")
+                        .short('e')
+                        .long("engine")
+                        .value_name("PATH")
+                        .help("The path to the USI engine executable")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("working_dir")
+                        .short('w')
+                        .long("working-dir")
+                        .value_name("DIR")
+                        .help("The working directory when launching the engine")
+                        .num_args(1),
+                ),
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("engine-info", sub_matches)) => return engine_info(sub_matches),
+        Some(("init", sub_matches)) => return init(sub_matches),
+        Some(("check", sub_matches)) => return check(sub_matches),
+        _ => {}
+    }
+
     let mut match_config = MatchConfig::default();
     if let Some(config_path) = matches.get_one::<String>("config") {
-        match_config
-            .load(config_path)
-            .unwrap_or_else(|_| panic!("failed to open the config file at {config_path}"));
+        if let Err(e) = match_config.load(config_path) {
+            eprintln!("failed to load the config file at {config_path}: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(tc) = matches.get_one::<String>("tc") {
+        if let Err(e) = match_config.override_time_control(tc) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
     }
 
     if let Some(display) = matches.get_one::<String>("display") {
-        match_config.display = match display.as_str() {
-            "board" => DisplayMode::Board,
-            "command" => DisplayMode::Command,
-            "csa" => DisplayMode::Csa,
-            _ => DisplayMode::Simple,
+        match_config.display = display.parse().unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+    }
+    if matches.get_flag("sfen_details") {
+        match_config.sfen_details = true;
+    }
+    for record in matches.get_many::<String>("record").unwrap_or_default() {
+        let output = OutputConfig::record(record).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+        match_config.records.push(output);
+    }
+    if let Some(path) = matches.get_one::<String>("results_json") {
+        match_config.results_json = Some(PathBuf::from(path));
+    }
+    for log in matches.get_many::<String>("log").unwrap_or_default() {
+        let output = OutputConfig::log(log).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+        match_config.logs.push(output);
+    }
+
+    if let Err(e) = run_match(&match_config) {
+        eprintln!("an error occurred during the match: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn engine_info(matches: &ArgMatches) {
+    let engine_path = matches.get_one::<String>("engine").unwrap();
+    let working_dir = matches
+        .get_one::<String>("working_dir")
+        .map_or("", |v| v.as_str());
+
+    match EngineProfile::query(engine_path, working_dir) {
+        Ok(profile) => {
+            if matches.get_one::<String>("format").map(|v| v.as_str()) == Some("json") {
+                println!("{}", profile.to_json());
+            } else {
+                print!("{}", profile.to_table());
+            }
+        }
+        Err(e) => {
+            eprintln!("failed to query the engine information: {e}");
+            std::process::exit(1);
         }
     }
+}
 
-    Factory::init();
+fn init(matches: &ArgMatches) {
+    // Relative paths would be resolved against the working directory of the engines.
+    let black_path = &absolute_engine_path(matches.get_one::<String>("black").unwrap());
+    let white_path = &absolute_engine_path(matches.get_one::<String>("white").unwrap());
+
+    let query = |path: &str| {
+        EngineProfile::query(path, &default_working_dir(path)).map_err(|e| {
+            eprintln!("failed to query the engine information of {path}: {e}");
+        })
+    };
+    let (Ok(black), Ok(white)) = (query(black_path), query(white_path)) else {
+        std::process::exit(1);
+    };
+
+    let config = generate_config(black_path, &black, white_path, &white);
+    match matches.get_one::<String>("output") {
+        Some(output) => {
+            let result = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(output)
+                .and_then(|mut f| f.write_all(config.as_bytes()));
+            if let Err(e) = result {
+                eprintln!("failed to write the config file at {output}: {e}");
+                std::process::exit(1);
+            }
+        }
+        None => print!("{config}"),
+    }
+}
+
+fn check(matches: &ArgMatches) {
+    let engine_path = matches.get_one::<String>("engine").unwrap();
+    let working_dir = matches
+        .get_one::<String>("working_dir")
+        .map_or("", |v| v.as_str());
 
-    match run_match(&match_config) {
-        Ok(_) => {}
+    let results = match run_checks(engine_path, working_dir) {
+        Ok(results) => results,
         Err(e) => {
-            println!("an error occurred during the match: {e}");
+            eprintln!("failed to launch the engine: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut num_passed = 0;
+    for result in &results {
+        let ms = result.elapsed.as_millis();
+        match result.outcome {
+            Ok(ref detail) => {
+                num_passed += 1;
+                println!("[PASS] {} ({ms}ms): {detail}", result.name);
+            }
+            Err(ref reason) => {
+                println!("[FAIL] {} ({ms}ms): {reason}", result.name);
+            }
         }
     }
+    println!("{num_passed}/{} scenarios passed", results.len());
+
+    if num_passed < results.len() {
+        std::process::exit(1);
+    }
 }
 
 fn run_match(config: &MatchConfig) -> Result<MatchStatistics, Error> {
-    let mut env = Environment::new().max_ply(config.max_ply);
+    // Printed to stderr so that records written to stdout are kept intact.
+    eprintln!("Time control: {}", config.time_control()?);
+    let start = Instant::now();
+
+    let mut env = Environment::new()
+        .max_ply(config.max_ply)
+        .time_margin(config.time_margin)
+        .clock_mode(config.clock_mode);
     let mut stats = MatchStatistics::new(config.num_games);
 
     let black_state = Arc::new(RwLock::new(ThinkState::default()));
     let white_state = Arc::new(RwLock::new(ThinkState::default()));
 
-    let reporter: Arc<Mutex<dyn Reporter + Send>> = match config.display {
-        DisplayMode::Board => Arc::new(Mutex::new(BoardReporter::new(
-            black_state.clone(),
-            white_state.clone(),
-        ))),
-        DisplayMode::Command => Arc::new(Mutex::new(UsiReporter::default())),
-        DisplayMode::Csa => Arc::new(Mutex::new(CsaReporter::default())),
-        DisplayMode::Simple => Arc::new(Mutex::new(SimpleReporter::default())),
+    let new_reporter = |mode: DisplayMode, writer: RecordWriter| -> Box<dyn Reporter + Send> {
+        match mode {
+            DisplayMode::Board => {
+                Box::new(BoardReporter::new(black_state.clone(), white_state.clone()))
+            }
+            DisplayMode::Command => Box::<UsiReporter>::default(),
+            DisplayMode::Csa => Box::new(CsaReporter::new(writer)),
+            DisplayMode::Kif => Box::new(KifReporter::new(writer)),
+            DisplayMode::Ki2 => Box::new(Ki2Reporter::new(writer)),
+            DisplayMode::Jkf => Box::new(JkfReporter::new(writer)),
+            DisplayMode::Jsonl => Box::new(JsonlReporter::new(writer)),
+            DisplayMode::Sfen => Box::new(SfenReporter::new(writer, config.sfen_details)),
+            DisplayMode::Simple => Box::<SimpleReporter>::default(),
+        }
+    };
+    let output_writer = |output: &OutputConfig| {
+        let writer = match output.dir() {
+            Some(dir) => RecordWriter::new(Some(dir.to_path_buf()), config.filename.clone()),
+            None => RecordWriter::file(output.path.clone()),
+        };
+        writer.hide_progress()
+    };
+
+    // Events of the whole match are written to stdout even if the output directory is set.
+    let display_writer = if config.display == DisplayMode::Jsonl {
+        RecordWriter::default()
+    } else {
+        RecordWriter::new(config.output_dir.clone(), config.filename.clone())
+    };
+    let display = new_reporter(config.display, display_writer);
+    let mut outputs = config
+        .records
+        .iter()
+        .map(|output| new_reporter(output.mode, output_writer(output)))
+        .collect::<Vec<_>>();
+    outputs.extend(config.logs.iter().map(|output| {
+        Box::new(UsiLogReporter::new(
+            output_writer(output),
+            output_writer(output),
+        )) as Box<dyn Reporter + Send>
+    }));
+    let reporter: Arc<Mutex<dyn Reporter + Send>> =
+        Arc::new(Mutex::new(MultiReporter::new(display, outputs)));
+    reporter.lock().unwrap().on_match_started(config);
+    // Stderr of the engines is inherited unless it is displayed along with commands or logged.
+    let capture_stderr = config.display == DisplayMode::Command || !config.logs.is_empty();
+    let new_engine = |color: Color, env: &Environment| {
+        let (engine_config, think_state) = if color == Color::Black {
+            (&config.black_engine, &black_state)
+        } else {
+            (&config.white_engine, &white_state)
+        };
+        UsiEngine::new(
+            color,
+            engine_config,
+            env.new_sender(),
+            Some(create_read_hook(color, reporter.clone())),
+            Some(create_write_hook(color, reporter.clone())),
+            capture_stderr.then(|| create_stderr_hook(color, reporter.clone())),
+            think_state.clone(),
+        )
     };
-    let mut black_engine = UsiEngine::new(
-        Color::Black,
-        &config.black_engine,
-        env.new_sender(),
-        Some(create_read_hook(Color::Black, reporter.clone())),
-        black_state,
-    )?;
-    let mut white_engine = UsiEngine::new(
-        Color::White,
-        &config.white_engine,
-        env.new_sender(),
-        Some(create_read_hook(Color::Black, reporter.clone())),
-        white_state,
-    )?;
+    let mut black_engine = new_engine(Color::Black, &env)?;
+    let mut white_engine = new_engine(Color::White, &env)?;
 
     for _ in 0..config.num_games {
         let result = env.start_game(
@@ -108,11 +424,26 @@ fn run_match(config: &MatchConfig) -> Result<MatchStatistics, Error> {
             &mut white_engine,
             reporter.clone(),
         )?;
-        stats.record_game(result.winner);
+        stats.record_game(&result);
+
+        // The engine which has been disconnected during the game is restarted for the next game.
+        if result.reason == GameOverReason::Disconnected {
+            match result.winner {
+                Some(Color::White) => black_engine = new_engine(Color::Black, &env)?,
+                Some(Color::Black) => white_engine = new_engine(Color::White, &env)?,
+                None => {}
+            }
+        }
     }
 
     reporter.lock().unwrap().on_match_finished(&stats);
 
+    if let Some(ref path) = config.results_json {
+        let names = (black_engine.name.as_str(), white_engine.name.as_str());
+        let results = match_results(config, &stats, names, start.elapsed())?;
+        fs::write(path, format!("{results}\n"))?;
+    }
+
     Ok(stats)
 }
 
@@ -127,3 +458,9 @@ fn create_read_hook(color: Color, reporter: Arc<Mutex<dyn Reporter + Send>>) ->
         Ok(())
     })
 }
+
+fn create_stderr_hook(color: Color, reporter: Arc<Mutex<dyn Reporter + Send>>) -> StderrHookFn {
+    Box::new(move |line| {
+        reporter.lock().unwrap().on_engine_stderr(color, line);
+    })
+}
@@ -0,0 +1,321 @@
+use shogi::bitboard::Factory;
+use shogi::Color;
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{DisplayMode, MatchConfig};
+use crate::create_read_hook;
+use crate::engine::{ThinkState, UsiEngine};
+use crate::environment::{create_write_hook, Environment};
+use crate::error::Error;
+use crate::game::GameResult;
+use crate::process::EngineProcess;
+use crate::reporter::Reporter;
+use crate::stats::MatchStatistics;
+
+/// Represents a scripted response to `go` or `ponderhit` command.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    /// Returns `bestmove` with the move.
+    Move(&'static str),
+    /// Returns `bestmove` with the move and the ponder move.
+    MoveWithPonder(&'static str, &'static str),
+    Resign,
+    Win,
+    /// Closes the output as if the engine crashed.
+    Crash,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    delay: Duration,
+    info: Vec<String>,
+    reply: Reply,
+    after: Vec<String>,
+}
+
+/// A USI engine running in-process, which replays scripted responses.
+///
+/// Each `go` command, or `ponderhit` command while pondering, consumes one reply from the script.
+/// The engine resigns once the script runs out.
+#[derive(Debug, Clone, Default)]
+pub struct MockEngine {
+    name: String,
+    steps: VecDeque<Step>,
+    stop_delay: Duration,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockEngine {
+    pub fn new(name: &str) -> MockEngine {
+        MockEngine {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn then(self, reply: Reply) -> MockEngine {
+        self.then_after(Duration::from_secs(0), reply)
+    }
+
+    /// Adds a reply returned after thinking for `delay`.
+    pub fn then_after(mut self, delay: Duration, reply: Reply) -> MockEngine {
+        self.steps.push_back(Step {
+            delay,
+            info: Vec::new(),
+            reply,
+            after: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds an `info` command sent before the last reply, e.g. `depth 10 nodes 1000`.
+    pub fn info(mut self, params: &str) -> MockEngine {
+        if let Some(step) = self.steps.back_mut() {
+            step.info.push(format!("info {params}"));
+        }
+        self
+    }
+
+    /// Adds a raw line sent right after the last reply, e.g. an unsolicited `bestmove`.
+    pub fn unsolicited(mut self, line: &str) -> MockEngine {
+        if let Some(step) = self.steps.back_mut() {
+            step.after.push(line.to_string());
+        }
+        self
+    }
+
+    /// Delays `bestmove` after `stop` while pondering, as engines take time to stop searching.
+    pub fn stop_after(mut self, delay: Duration) -> MockEngine {
+        self.stop_delay = delay;
+        self
+    }
+
+    /// Returns commands received by the engine so far.
+    pub fn log(&self) -> Arc<Mutex<Vec<String>>> {
+        self.log.clone()
+    }
+
+    /// Starts the engine in a new thread.
+    pub fn spawn(self) -> EngineProcess {
+        let (in_tx, in_rx) = channel::<String>();
+        let (out_tx, out_rx) = channel::<Vec<u8>>();
+
+        thread::spawn(move || self.run(in_rx, out_tx));
+
+        EngineProcess::from_io(
+            BufReader::new(MockReader {
+                rx: out_rx,
+                buf: Vec::new(),
+            }),
+            MockWriter {
+                tx: in_tx,
+                buf: Vec::new(),
+            },
+        )
+    }
+
+    fn run(mut self, commands: Receiver<String>, out: Sender<Vec<u8>>) {
+        let write = |s: &str| {
+            let _ = out.send(format!("{s}\n").into_bytes());
+        };
+        let mut pondering = false;
+
+        for cmd in commands.iter() {
+            self.log.lock().unwrap().push(cmd.to_string());
+
+            let reply = match cmd.split_whitespace().next().unwrap_or_default() {
+                "usi" => {
+                    write(&format!("id name {}", self.name));
+                    write("id author mock");
+                    write("option name USI_Hash type spin default 16 min 1 max 1024");
+                    write("usiok");
+                    None
+                }
+                "isready" => {
+                    write("readyok");
+                    None
+                }
+                "go" if cmd.contains(" ponder") => {
+                    pondering = true;
+                    None
+                }
+                "go" => self.steps.pop_front(),
+                "ponderhit" if pondering => {
+                    pondering = false;
+                    self.steps.pop_front()
+                }
+                "stop" if pondering => {
+                    pondering = false;
+                    thread::sleep(self.stop_delay);
+                    // The move is discarded by GUI after stopping ponder.
+                    write("bestmove 1g1f");
+                    None
+                }
+                "quit" => break,
+                _ => None,
+            };
+
+            let is_search = cmd.starts_with("go") || cmd == "ponderhit";
+            if reply.is_none() && is_search && !pondering {
+                write("bestmove resign");
+            }
+
+            if let Some(step) = reply {
+                thread::sleep(step.delay);
+                for info in &step.info {
+                    write(info);
+                }
+
+                match step.reply {
+                    Reply::Move(m) => write(&format!("bestmove {m}")),
+                    Reply::MoveWithPonder(m, p) => write(&format!("bestmove {m} ponder {p}")),
+                    Reply::Resign => write("bestmove resign"),
+                    Reply::Win => write("bestmove win"),
+                    Reply::Crash => return,
+                }
+                for line in &step.after {
+                    write(line);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the configuration of a game with 5 seconds of byoyomi and no main time.
+pub fn config() -> MatchConfig {
+    let mut config = MatchConfig {
+        display: DisplayMode::Simple,
+        ..Default::default()
+    };
+    config.time.black_time = Duration::from_secs(0);
+    config.time.white_time = Duration::from_secs(0);
+    config.time.black_inc = None;
+    config.time.white_inc = None;
+    config.time.black_byoyomi = Some(Duration::from_secs(5));
+    config.time.white_byoyomi = Some(Duration::from_secs(5));
+    config
+}
+
+/// Plays a game between the mock engines, where events and commands are sent to the reporter.
+pub fn play_game(
+    config: &MatchConfig,
+    black: MockEngine,
+    white: MockEngine,
+    reporter: Arc<Mutex<dyn Reporter + Send>>,
+) -> Result<GameResult, Error> {
+    Factory::init();
+
+    let mut env = Environment::new()
+        .max_ply(config.max_ply)
+        .time_margin(config.time_margin)
+        .clock_mode(config.clock_mode);
+    let engine = |color: Color, mock: MockEngine| {
+        let engine_config = if color == Color::Black {
+            &config.black_engine
+        } else {
+            &config.white_engine
+        };
+        UsiEngine::with_process(
+            color,
+            engine_config,
+            mock.spawn(),
+            env.new_sender(),
+            Some(create_read_hook(color, reporter.clone())),
+            Some(create_write_hook(color, reporter.clone())),
+            Arc::new(RwLock::new(ThinkState::default())),
+        )
+    };
+    let mut black_engine = engine(Color::Black, black)?;
+    let mut white_engine = engine(Color::White, white)?;
+
+    let stats = MatchStatistics::new(1);
+    env.start_game(
+        config,
+        &stats,
+        &mut black_engine,
+        &mut white_engine,
+        reporter,
+    )
+}
+
+/// A directory for files written by a test, which is removed with its contents when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty directory in the temporary directory, which is unique to the process.
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("usirun_{name}_{}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+struct MockReader {
+    rx: Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+impl Read for MockReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.buf.is_empty() {
+            match self.rx.recv() {
+                Ok(bytes) => self.buf = bytes,
+                // The engine has exited.
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let n = out.len().min(self.buf.len());
+        out[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        Ok(n)
+    }
+}
+
+struct MockWriter {
+    tx: Sender<String>,
+    buf: Vec<u8>,
+}
+
+impl Write for MockWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(bytes);
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line = self.buf.drain(..=pos).collect::<Vec<_>>();
+            // Commands sent after the engine exited are discarded like a closed pipe buffer.
+            let _ = self
+                .tx
+                .send(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use usi::{EngineCommand, EngineCommandReader, EngineOutput, GuiCommand};
+
+use crate::error::Error;
+
+/// A USI engine process whose outputs are read in a background thread.
+///
+/// Unlike `usi::UsiEngineHandler`, outputs are queued in a channel so that callers can wait
+/// for a specific command with a timeout.
+pub struct EngineProcess {
+    child: Option<Child>,
+    stdin: Box<dyn Write + Send>,
+    outputs: Option<Receiver<EngineOutput>>,
+}
+
+impl EngineProcess {
+    pub fn spawn(engine_path: &str, working_dir: &str) -> Result<EngineProcess, Error> {
+        EngineProcess::spawn_child(engine_path, working_dir, Stdio::inherit())
+    }
+
+    /// Spawns the engine process, passing each line written to its stderr to `stderr_hook`
+    /// in a background thread instead of inheriting stderr.
+    pub fn spawn_with_stderr<F>(
+        engine_path: &str,
+        working_dir: &str,
+        mut stderr_hook: F,
+    ) -> Result<EngineProcess, Error>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        let mut process = EngineProcess::spawn_child(engine_path, working_dir, Stdio::piped())?;
+
+        if let Some(stderr) = process.child.as_mut().and_then(|child| child.stderr.take()) {
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines() {
+                    match line {
+                        Ok(line) => stderr_hook(&line),
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+        Ok(process)
+    }
+
+    fn spawn_child(
+        engine_path: &str,
+        working_dir: &str,
+        stderr: Stdio,
+    ) -> Result<EngineProcess, Error> {
+        let mut command = Command::new(engine_path);
+        if !working_dir.is_empty() {
+            command.current_dir(working_dir);
+        }
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let mut process = EngineProcess::from_io(BufReader::new(stdout), stdin);
+        process.child = Some(child);
+        Ok(process)
+    }
+
+    /// Creates a new instance communicating with an engine through the given reader and writer
+    /// instead of a child process.
+    pub fn from_io<R, W>(reader: R, writer: W) -> EngineProcess
+    where
+        R: BufRead + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let mut reader = EngineCommandReader::new(reader);
+            loop {
+                match reader.next_command() {
+                    Ok(output) => {
+                        // An empty output without any response means EOF.
+                        let eof = output.response().is_none() && output.raw_str().is_empty();
+                        if eof || tx.send(output).is_err() {
+                            break;
+                        }
+                    }
+                    Err(usi::Error::IllegalSyntax) => continue,
+                    Err(_) => break,
+                }
+            }
+        });
+
+        EngineProcess {
+            child: None,
+            stdin: Box::new(writer),
+            outputs: Some(rx),
+        }
+    }
+
+    /// Sends a command to the engine.
+    pub fn send(&mut self, command: &GuiCommand) -> Result<(), Error> {
+        self.send_raw(&command.to_string())
+    }
+
+    /// Sends a raw command string, which may contain parameters not supported by `GuiCommand`.
+    pub fn send_raw(&mut self, command: &str) -> Result<(), Error> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Returns the next output from the engine, or `None` if nothing arrives within `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<EngineOutput>, Error> {
+        let outputs = self
+            .outputs
+            .as_ref()
+            .ok_or(Error::Usi(usi::Error::IllegalOperation))?;
+
+        match outputs.recv_timeout(timeout) {
+            Ok(output) => Ok(Some(output)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(e) => Err(Error::Channel(Box::new(e))),
+        }
+    }
+
+    /// Waits until the engine returns a command matching with `pred`.
+    /// Any outputs received in the meantime are passed to `on_output`.
+    pub fn wait_for<P, F>(
+        &self,
+        timeout: Duration,
+        mut pred: P,
+        mut on_output: F,
+    ) -> Result<EngineOutput, Error>
+    where
+        P: FnMut(&EngineCommand) -> bool,
+        F: FnMut(&EngineOutput),
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.recv_timeout(remaining)? {
+                Some(output) => {
+                    if output.response().as_ref().is_some_and(&mut pred) {
+                        return Ok(output);
+                    }
+                    on_output(&output);
+                }
+                None => return Err(Error::Timeout),
+            }
+        }
+    }
+
+    /// Spawns a new thread calling `hook` for each output from the engine.
+    /// `on_close` is called once the engine closes its output or `hook` returns an error.
+    ///
+    /// Other methods receiving outputs can not be used after calling this method.
+    pub fn listen<F, G>(&mut self, mut hook: F, on_close: G) -> Result<(), Error>
+    where
+        F: FnMut(&EngineOutput) -> Result<(), Error> + Send + 'static,
+        G: FnOnce() + Send + 'static,
+    {
+        let outputs = self
+            .outputs
+            .take()
+            .ok_or(Error::Usi(usi::Error::IllegalOperation))?;
+
+        thread::spawn(move || {
+            for output in outputs.iter() {
+                if hook(&output).is_err() {
+                    break;
+                }
+            }
+            on_close();
+        });
+
+        Ok(())
+    }
+
+    /// Waits for the engine process to exit, returning false if it is still running after `timeout`.
+    pub fn wait_exit(&mut self, timeout: Duration) -> Result<bool, Error> {
+        let Some(ref mut child) = self.child else {
+            return Ok(true);
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if child.try_wait()?.is_some() {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send(&GuiCommand::Quit);
+
+        if let Some(ref mut child) = self.child {
+            if let Ok(None) = child.try_wait() {
+                let _ = child.kill();
+            }
+            let _ = child.wait();
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::time::Duration;
+use usi::{EngineCommand, EngineOutput, GuiCommand, IdParams, OptionKind, OptionParams};
+
+use crate::error::Error;
+use crate::json::JsonValue;
+use crate::process::EngineProcess;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Represents the identity and options reported by an engine in response to `usi` command.
+#[derive(Debug, Default)]
+pub struct EngineProfile {
+    pub name: String,
+    pub author: String,
+    pub options: Vec<OptionParams>,
+}
+
+impl EngineProfile {
+    /// Launches the engine and performs the handshake (`usi` and `isready`) to query its profile.
+    pub fn query(engine_path: &str, working_dir: &str) -> Result<EngineProfile, Error> {
+        let mut process = EngineProcess::spawn(engine_path, working_dir)?;
+        let profile = EngineProfile::handshake(&mut process)?;
+        process.send(&GuiCommand::Quit)?;
+
+        Ok(profile)
+    }
+
+    /// Performs the handshake with the given process and returns the profile of the engine.
+    pub fn handshake(process: &mut EngineProcess) -> Result<EngineProfile, Error> {
+        EngineProfile::handshake_with(process, |_, _| {}, |_| Ok(()))
+    }
+
+    /// Performs the handshake, passing each command sent to `on_send` and each output received
+    /// to `on_receive`, e.g. to report them.
+    pub fn handshake_with<S, R>(
+        process: &mut EngineProcess,
+        mut on_send: S,
+        mut on_receive: R,
+    ) -> Result<EngineProfile, Error>
+    where
+        S: FnMut(&GuiCommand, &str),
+        R: FnMut(&EngineOutput) -> Result<(), Error>,
+    {
+        let mut profile = EngineProfile::default();
+        // Only the first error of the hook is returned after the handshake.
+        let mut hook_error = None;
+        let mut receive = |output: &EngineOutput| {
+            if hook_error.is_none() {
+                hook_error = on_receive(output).err();
+            }
+        };
+
+        on_send(&GuiCommand::Usi, &GuiCommand::Usi.to_string());
+        process.send(&GuiCommand::Usi)?;
+        let usiok = process.wait_for(
+            HANDSHAKE_TIMEOUT,
+            |cmd| *cmd == EngineCommand::UsiOk,
+            |output| {
+                receive(output);
+                match output.response() {
+                    Some(EngineCommand::Id(IdParams::Name(name))) => {
+                        profile.name = name.to_string();
+                    }
+                    Some(EngineCommand::Id(IdParams::Author(author))) => {
+                        profile.author = author.to_string();
+                    }
+                    Some(EngineCommand::Option(params)) => {
+                        profile.options.push(params.clone());
+                    }
+                    _ => {}
+                }
+            },
+        )?;
+        receive(&usiok);
+
+        on_send(&GuiCommand::IsReady, &GuiCommand::IsReady.to_string());
+        process.send(&GuiCommand::IsReady)?;
+        let readyok = process
+            .wait_for(
+                HANDSHAKE_TIMEOUT,
+                |cmd| *cmd == EngineCommand::ReadyOk,
+                &mut receive,
+            )
+            .map_err(|_| Error::EngineNotResponded)?;
+        receive(&readyok);
+
+        if let Some(e) = hook_error {
+            return Err(e);
+        }
+        Ok(profile)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let options = self
+            .options
+            .iter()
+            .map(|opt| {
+                let (min, max) = match opt.value {
+                    OptionKind::Spin { min, max, .. } => (min, max),
+                    _ => (None, None),
+                };
+                let default: JsonValue = match opt.value {
+                    OptionKind::Check { default } => default.into(),
+                    OptionKind::Spin { default, .. } => default.into(),
+                    ref kind => option_default(kind).into(),
+                };
+                let vars = match opt.value {
+                    OptionKind::Combo { ref vars, .. } => Some(combo_vars(vars)),
+                    _ => None,
+                };
+
+                JsonValue::object()
+                    .field("name", opt.name.as_str())
+                    .field("type", option_type(&opt.value))
+                    .field("default", default)
+                    .field("min", min)
+                    .field("max", max)
+                    .field("vars", vars)
+            })
+            .collect::<Vec<_>>();
+
+        JsonValue::object()
+            .field("name", self.name.as_str())
+            .field("author", self.author.as_str())
+            .field("options", options)
+    }
+
+    pub fn to_table(&self) -> String {
+        let header = ["Name", "Type", "Default", "Min", "Max", "Vars"].map(String::from);
+        let mut rows = vec![header];
+        for opt in &self.options {
+            let (min, max) = match opt.value {
+                OptionKind::Spin { min, max, .. } => (
+                    min.map(|v| v.to_string()).unwrap_or_default(),
+                    max.map(|v| v.to_string()).unwrap_or_default(),
+                ),
+                _ => (String::new(), String::new()),
+            };
+            let vars = match opt.value {
+                OptionKind::Combo { ref vars, .. } => combo_vars(vars).join(" "),
+                _ => String::new(),
+            };
+
+            rows.push([
+                opt.name.to_string(),
+                option_type(&opt.value).to_string(),
+                option_default(&opt.value).unwrap_or_default(),
+                min,
+                max,
+                vars,
+            ]);
+        }
+
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (w, col) in widths.iter_mut().zip(row.iter()) {
+                *w = (*w).max(col.chars().count());
+            }
+        }
+
+        let mut s = format!("Name:   {}\nAuthor: {}\n\n", self.name, self.author);
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(col, w)| format!("{col:w$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            s.push_str(line.trim_end());
+            s.push('\n');
+        }
+        s
+    }
+}
+
+/// Returns the type name of the option as written in `option` command.
+pub fn option_type(kind: &OptionKind) -> &'static str {
+    match *kind {
+        OptionKind::Check { .. } => "check",
+        OptionKind::Spin { .. } => "spin",
+        OptionKind::Combo { .. } => "combo",
+        OptionKind::Button { .. } => "button",
+        OptionKind::String { .. } => "string",
+        OptionKind::Filename { .. } => "filename",
+    }
+}
+
+/// Returns the default value of the option as written in `option` command.
+pub fn option_default(kind: &OptionKind) -> Option<String> {
+    match *kind {
+        OptionKind::Check { default } => default.map(|v| v.to_string()),
+        OptionKind::Spin { default, .. } => default.map(|v| v.to_string()),
+        OptionKind::Combo { ref default, .. }
+        | OptionKind::Button { ref default }
+        | OptionKind::String { ref default }
+        | OptionKind::Filename { ref default } => default.clone(),
+    }
+}
+
+/// Checks whether the value of `setoption` command matches the type of the option.
+pub fn validate_option(kind: &OptionKind, value: &str) -> Result<(), String> {
+    let expected = match *kind {
+        OptionKind::Check { .. } if value == "true" || value == "false" => return Ok(()),
+        OptionKind::Check { .. } => "\"true\" or \"false\"".to_string(),
+        OptionKind::Spin { min, max, .. } => match value.parse::<i32>() {
+            Ok(v) if min.is_none_or(|min| v >= min) && max.is_none_or(|max| v <= max) => {
+                return Ok(())
+            }
+            _ => format!(
+                "an integer between {} and {}",
+                min.map_or("-".to_string(), |v| v.to_string()),
+                max.map_or("-".to_string(), |v| v.to_string())
+            ),
+        },
+        OptionKind::Combo { ref vars, .. } => {
+            let vars = combo_vars(vars);
+            if vars.contains(&value) {
+                return Ok(());
+            }
+            format!("one of {}", vars.join(", "))
+        }
+        OptionKind::Button { .. } => "no value for a button".to_string(),
+        OptionKind::String { .. } | OptionKind::Filename { .. } => return Ok(()),
+    };
+
+    Err(format!("expected {expected}"))
+}
+
+/// Returns candidate values of a combo option.
+///
+/// `usi` crate keeps `var` keywords in the list, which are removed here.
+pub fn combo_vars(vars: &[String]) -> Vec<&str> {
+    vars.iter()
+        .map(|v| v.as_str())
+        .filter(|v| *v != "var")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEngine;
+
+    #[test]
+    fn handshake() {
+        let mut process = MockEngine::new("Mock Engine").spawn();
+        let profile = EngineProfile::handshake(&mut process).unwrap();
+
+        assert_eq!("Mock Engine", profile.name);
+        assert_eq!("mock", profile.author);
+        assert_eq!(1, profile.options.len());
+        assert_eq!("USI_Hash", profile.options[0].name);
+        assert_eq!(
+            OptionKind::Spin {
+                default: Some(16),
+                min: Some(1),
+                max: Some(1024)
+            },
+            profile.options[0].value
+        );
+        assert_eq!(
+            r#"{"name":"Mock Engine","author":"mock","options":[{"name":"USI_Hash","type":"spin","default":16,"min":1,"max":1024,"vars":null}]}"#,
+            profile.to_json().to_string()
+        );
+    }
+
+    #[test]
+    fn validate_option_values() {
+        let spin = OptionKind::Spin {
+            default: Some(16),
+            min: Some(1),
+            max: Some(1024),
+        };
+        assert!(validate_option(&spin, "256").is_ok());
+        assert!(validate_option(&spin, "0").is_err());
+        assert!(validate_option(&spin, "0.5").is_err());
+        assert!(validate_option(&spin, "2.0").is_err());
+
+        let check = OptionKind::Check {
+            default: Some(true),
+        };
+        assert!(validate_option(&check, "false").is_ok());
+        assert!(validate_option(&check, "1").is_err());
+
+        let combo = OptionKind::Combo {
+            default: Some("Normal".to_string()),
+            vars: ["var", "Solid", "var", "Normal"].map(String::from).to_vec(),
+        };
+        assert!(validate_option(&combo, "Solid").is_ok());
+        assert!(validate_option(&combo, "Risky").is_err());
+        assert!(validate_option(&combo, "var").is_err());
+
+        let string = OptionKind::String { default: None };
+        assert!(validate_option(&string, "0.5").is_ok());
+        assert!(validate_option(&OptionKind::Button { default: None }, "true").is_err());
+    }
+}
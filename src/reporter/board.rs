@@ -5,8 +5,8 @@ use crate::environment::Event;
 use crate::stats::MatchStatistics;
 use shogi::Color;
 
-use super::Reporter;
-use crate::engine::ThinkState;
+use super::{print_summary, Reporter};
+use crate::engine::{SearchInfo, ThinkState};
 use crate::game::{Game, GameOverReason};
 
 pub struct BoardReporter {
@@ -43,7 +43,7 @@ impl BoardReporter {
         let term = Term::stderr();
 
         if self.dirty {
-            term.clear_last_lines(27)?;
+            term.clear_last_lines(28)?;
             self.dirty = false;
         }
 
@@ -53,14 +53,27 @@ impl BoardReporter {
             stats.total_games()
         ))?;
         term.write_line(&format!("{}", game.pos))?;
+        let clock = |c: Color| match game.time.moves_to_go(c) {
+            Some(n) => format!("{}s/{n} moves", game.time.remaining(c).as_secs()),
+            None => format!("{}s", game.time.remaining(c).as_secs()),
+        };
         term.write_line(&format!(
-            "Time: (Black) {}s, (White) {}s",
-            game.time.black_time().as_secs(),
-            game.time.white_time().as_secs()
+            "Time: (Black) {}, (White) {}",
+            clock(Color::Black),
+            clock(Color::White)
         ))?;
         term.write_line(&format!(
             "Score (Black) {black_score}, (White) {white_score}"
         ))?;
+        let search = |c: Color| match game.moves.iter().rev().find(|m| m.color == c) {
+            Some(m) => format_search(&m.info),
+            None => "-".to_string(),
+        };
+        term.write_line(&format!(
+            "Search (Black) {}, (White) {}",
+            search(Color::Black),
+            search(Color::White)
+        ))?;
         self.dirty = true;
 
         Ok(())
@@ -75,7 +88,7 @@ impl BoardReporter {
         let term = Term::stderr();
 
         if self.dirty {
-            term.clear_last_lines(27)?;
+            term.clear_last_lines(28)?;
             self.dirty = false;
         }
 
@@ -97,6 +110,19 @@ impl BoardReporter {
     }
 }
 
+fn format_search(info: &SearchInfo) -> String {
+    let depth = match (info.depth, info.seldepth) {
+        (Some(depth), Some(seldepth)) => format!("depth {depth}/{seldepth}"),
+        (Some(depth), None) => format!("depth {depth}"),
+        _ => "depth -".to_string(),
+    };
+    let knps = info
+        .nps
+        .map_or("-".to_string(), |nps| (nps / 1000).to_string());
+
+    format!("{depth} {knps}knps")
+}
+
 impl Reporter for BoardReporter {
     fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
         match *event {
@@ -109,4 +135,8 @@ impl Reporter for BoardReporter {
             _ => {}
         }
     }
+
+    fn on_match_finished(&mut self, stats: &MatchStatistics) {
+        print_summary(stats);
+    }
 }
@@ -1,16 +1,57 @@
 use csa::{Action, Color, GameRecord, MoveRecord, PieceType, Square, Time};
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif::ProgressBar;
+use shogi::MoveError;
+use std::mem;
+use usi::ScoreKind;
 
-use crate::environment::Event;
-use crate::game::GameOverReason;
+use crate::clock::TimeControl;
+use crate::config::SearchLimit;
+use crate::environment::{move_record, Event};
+use crate::game::{Game, GameOverReason};
 use crate::stats::MatchStatistics;
 
-use super::Reporter;
+use super::{RecordWriter, Reporter};
 
 #[derive(Default)]
 pub struct CsaReporter {
     current_bar: Option<ProgressBar>,
     record: GameRecord,
+    comments: Vec<String>,
+    move_comments: Vec<Option<String>>,
+    writer: RecordWriter,
+}
+
+impl CsaReporter {
+    pub fn new(writer: RecordWriter) -> CsaReporter {
+        CsaReporter {
+            writer: writer.extension("csa").separator("/\n"),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the record with the comments of the game between the header and the position,
+    /// and the comment of each move after the move.
+    fn format_record(&mut self) -> String {
+        let moves = mem::take(&mut self.record.moves);
+        let position = self.record.start_pos.to_string();
+        let header = self.record.to_string();
+
+        let mut record = header
+            .strip_suffix(&position)
+            .unwrap_or(&header)
+            .to_string();
+        for comment in &self.comments {
+            record.push_str(&format!("{comment}\n"));
+        }
+        record.push_str(&position);
+        for (i, m) in moves.iter().enumerate() {
+            record.push_str(&m.to_string());
+            if let Some(Some(comment)) = self.move_comments.get(i) {
+                record.push_str(&format!("{comment}\n"));
+            }
+        }
+        record
+    }
 }
 
 fn convert_color(c: shogi::Color) -> Color {
@@ -20,7 +61,7 @@ fn convert_color(c: shogi::Color) -> Color {
     }
 }
 
-fn convert_pt(pt: shogi::PieceType) -> PieceType {
+pub(super) fn convert_pt(pt: shogi::PieceType) -> PieceType {
     match pt {
         shogi::PieceType::Pawn => PieceType::Pawn,
         shogi::PieceType::Lance => PieceType::Lance,
@@ -70,6 +111,63 @@ fn convert_move_to_action(c: shogi::Color, m: &shogi::MoveRecord) -> Action {
     }
 }
 
+/// The score written for mates, which floodgate-style comments have no notation for.
+const MATE_SCORE: i32 = 100_000;
+
+/// Returns the floodgate-style comment of the last move with the score from Black's perspective
+/// and the principal variation following the move, e.g. "'** 120 -3334FU +2726FU".
+fn score_comment(game: &Game) -> Option<String> {
+    let info = &game.moves.last()?.info;
+    let (value, ref kind) = *info.score.as_ref()?;
+    let score = match *kind {
+        ScoreKind::CpExact | ScoreKind::CpLowerbound | ScoreKind::CpUpperbound => value,
+        _ if value > 0 => MATE_SCORE,
+        _ => -MATE_SCORE,
+    };
+    let mover = game.pos.side_to_move().flip();
+    let score = if mover == shogi::Color::Black {
+        score
+    } else {
+        -score
+    };
+
+    // The PV is replayed from the position after the move to know which pieces move.
+    let mut pos = shogi::Position::new();
+    pos.set_sfen(&game.pos.to_sfen()).ok()?;
+    // The move ending the game by repetition is not kept in the position, but updates the board.
+    if let Some(m) = game.final_move.as_ref() {
+        let _ = pos.make_move(shogi::Move::from_sfen(&m.to_sfen())?);
+    }
+    let played = game.last_move().map(|m| m.to_sfen());
+    let mut pv = info.pv.iter().peekable();
+    if pv.peek().map(|m| Some(m.as_str()) == played.as_deref()) == Some(true) {
+        pv.next();
+    }
+
+    let mut comment = format!("'** {score}");
+    for m in pv {
+        let c = pos.side_to_move();
+        let Some((m, record)) =
+            shogi::Move::from_sfen(m).and_then(|m| Some((m, move_record(&pos, m)?)))
+        else {
+            break;
+        };
+        match pos.make_move(m) {
+            // The PV may repeat positions, which are not kept in the move history either.
+            Ok(_)
+            | Err(
+                MoveError::Repetition
+                | MoveError::PerpetualCheckLose
+                | MoveError::PerpetualCheckWin,
+            ) => {}
+            Err(_) => break,
+        }
+        comment.push_str(&format!(" {}", convert_move_to_action(c, &record)));
+    }
+
+    Some(comment)
+}
+
 impl Reporter for CsaReporter {
     fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
         match *event {
@@ -77,31 +175,35 @@ impl Reporter for CsaReporter {
                 let current_game_num = stats.finished_games() + 1;
                 let num_games = stats.total_games();
 
-                let pbar = ProgressBar::new_spinner();
-                pbar.set_draw_target(ProgressDrawTarget::stderr());
-                pbar.set_style(
-                    ProgressStyle::default_spinner()
-                        .tick_chars("|/-\\ ")
-                        .template("{prefix:.bold.dim} {spinner} {msg}")
-                        .unwrap(),
-                );
-                pbar.set_prefix(format!("[{current_game_num}/{num_games}]"));
-                pbar.set_message("Starting...");
-                self.current_bar = Some(pbar);
+                self.current_bar = Some(self.writer.spinner(current_game_num, num_games));
 
+                self.writer.begin(current_game_num, game);
                 self.record = GameRecord::default();
 
                 self.record.black_player = Some(game.black_player.to_string());
                 self.record.white_player = Some(game.white_player.to_string());
 
                 self.record.start_time = Some(Time::now());
+
+                self.comments.clear();
+                self.move_comments.clear();
+                // CSA records can not express moves-per-period time controls in the header.
+                if let TimeControl::MovesPerPeriod { .. } = game.time {
+                    self.comments.push(format!("'Time control: {}", game.time));
+                }
+                for (name, limit) in [("Black", game.black_limit), ("White", game.white_limit)] {
+                    if limit != SearchLimit::Clock {
+                        self.comments.push(format!("'{name} search limit: {limit}"));
+                    }
+                }
             }
             Event::NewTurn(ref game, elapsed) => {
-                if let Some(last_move) = game.pos.move_history().last() {
+                if let Some(last_move) = game.last_move() {
                     self.record.moves.push(MoveRecord {
                         action: convert_move_to_action(game.pos.side_to_move().flip(), last_move),
                         time: Some(elapsed),
                     });
+                    self.move_comments.push(score_comment(game));
                 }
 
                 if let Some(ref pbar) = self.current_bar {
@@ -115,6 +217,10 @@ impl Reporter for CsaReporter {
                     GameOverReason::OutOfTime => Action::TimeUp,
                     GameOverReason::MaxPly => Action::Hikiwake,
                     GameOverReason::DeclareWinning => Action::Kachi,
+                    GameOverReason::Repetition => Action::Sennichite,
+                    GameOverReason::PerpetualCheck | GameOverReason::Disconnected => {
+                        Action::IllegalMove
+                    }
                 };
                 self.record.moves.push(MoveRecord { action, time: None });
 
@@ -124,15 +230,159 @@ impl Reporter for CsaReporter {
                     pbar.finish_and_clear();
                 }
 
-                if stats.finished_games() > 1 {
-                    println!("/");
-                }
-
-                print!("{}", &self.record.to_string());
+                let record = self.format_record();
+                self.writer.write(&record);
             }
             _ => {}
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::SearchInfo;
+    use crate::environment::DEFAULT_SFEN;
+    use crate::game::MoveLog;
+    use shogi::bitboard::Factory;
+    use std::time::Duration;
+
+    /// Returns the game after the moves, where the last move was searched with the score and the PV.
+    fn game_after(moves: &[&str], score: (i32, ScoreKind), pv: &[&str]) -> Game {
+        Factory::init();
+
+        let mut game = Game::new(TimeControl::Byoyomi {
+            black_time: Duration::from_secs(0),
+            white_time: Duration::from_secs(0),
+            black_byoyomi: Duration::from_secs(0),
+            white_byoyomi: Duration::from_secs(0),
+        });
+        game.pos.set_sfen(DEFAULT_SFEN).unwrap();
+        for m in moves {
+            let color = game.pos.side_to_move();
+            let m = shogi::Move::from_sfen(m).unwrap();
+            let record = move_record(&game.pos, m);
+            if let Err(e) = game.pos.make_move(m) {
+                assert_eq!(MoveError::Repetition, e);
+                game.final_move = record;
+            }
+            game.moves.push(MoveLog {
+                color,
+                ply: game.pos.ply(),
+                elapsed: Duration::from_secs(0),
+                info: SearchInfo::default(),
+            });
+        }
+
+        let info = &mut game.moves.last_mut().unwrap().info;
+        info.score = Some(score);
+        info.pv = pv.iter().map(|m| m.to_string()).collect();
+        game
+    }
+
+    #[test]
+    fn score_from_black() {
+        let game = game_after(&["7g7f"], (120, ScoreKind::CpExact), &["7g7f", "3c3d"]);
+        assert_eq!(Some("'** 120 -3334FU".to_string()), score_comment(&game));
+
+        // Scores of White are negated.
+        let game = game_after(&["7g7f", "3c3d"], (50, ScoreKind::CpLowerbound), &[]);
+        assert_eq!(Some("'** -50".to_string()), score_comment(&game));
+        let game = game_after(&["7g7f", "3c3d"], (-50, ScoreKind::CpUpperbound), &[]);
+        assert_eq!(Some("'** 50".to_string()), score_comment(&game));
+    }
+
+    #[test]
+    fn mate_score() {
+        let game = game_after(&["7g7f"], (5, ScoreKind::MateExact), &[]);
+        assert_eq!(Some("'** 100000".to_string()), score_comment(&game));
+        let game = game_after(&["7g7f"], (-4, ScoreKind::MateExact), &[]);
+        assert_eq!(Some("'** -100000".to_string()), score_comment(&game));
+
+        let game = game_after(&["7g7f", "3c3d"], (1, ScoreKind::MateSignOnly), &[]);
+        assert_eq!(Some("'** -100000".to_string()), score_comment(&game));
+        let game = game_after(&["7g7f", "3c3d"], (-1, ScoreKind::MateSignOnly), &[]);
+        assert_eq!(Some("'** 100000".to_string()), score_comment(&game));
+    }
 
-    fn on_match_finished(&mut self, _: &MatchStatistics) {}
+    #[test]
+    fn pv_without_played_move() {
+        // The PV is written as is if it does not start with the played move.
+        let game = game_after(
+            &["7g7f", "3c3d"],
+            (0, ScoreKind::CpExact),
+            &["2g2f", "8c8d"],
+        );
+        assert_eq!(
+            Some("'** 0 +2726FU -8384FU".to_string()),
+            score_comment(&game)
+        );
+
+        let game = game_after(&["7g7f"], (0, ScoreKind::CpExact), &["7g7f"]);
+        assert_eq!(Some("'** 0".to_string()), score_comment(&game));
+    }
+
+    #[test]
+    fn pv_until_illegal_move() {
+        let game = game_after(
+            &["7g7f"],
+            (0, ScoreKind::CpExact),
+            &["7g7f", "3c3d", "3c3d", "2g2f"],
+        );
+        assert_eq!(Some("'** 0 -3334FU".to_string()), score_comment(&game));
+
+        let game = game_after(&["7g7f"], (0, ScoreKind::CpExact), &["3c3d", "xx", "2g2f"]);
+        assert_eq!(Some("'** 0 -3334FU".to_string()), score_comment(&game));
+    }
+
+    #[test]
+    fn pv_after_repetition() {
+        let cycle = ["5i4h", "5a4b", "4h5i", "4b5a"];
+        let game = game_after(
+            &cycle.repeat(3),
+            (0, ScoreKind::CpExact),
+            &["4b5a", "5i4h", "5a4b"],
+        );
+
+        // The PV follows the move causing the repetition, and repeats the positions again.
+        assert!(game.final_move.is_some());
+        assert_eq!(
+            Some("'** 0 +5948OU -5142OU".to_string()),
+            score_comment(&game)
+        );
+    }
+
+    #[test]
+    fn no_score() {
+        let mut game = game_after(&["7g7f"], (0, ScoreKind::CpExact), &["3c3d"]);
+        game.moves.last_mut().unwrap().info.score = None;
+        assert_eq!(None, score_comment(&game));
+    }
+
+    #[test]
+    fn comments_after_header() {
+        let mut reporter = CsaReporter::default();
+        reporter.record.black_player = Some("b".to_string());
+        reporter.record.white_player = Some("w".to_string());
+        reporter
+            .comments
+            .push("'Black search limit: depth 5".to_string());
+        reporter.record.moves.push(MoveRecord {
+            action: Action::Move(
+                Color::Black,
+                Square::new(7, 7),
+                Square::new(7, 6),
+                PieceType::Pawn,
+            ),
+            time: None,
+        });
+        reporter.move_comments.push(Some("'** 30".to_string()));
+
+        let record = reporter.format_record();
+        let position = reporter.record.start_pos.to_string();
+        assert_eq!(
+            format!("V2.2\nN+b\nN-w\n'Black search limit: depth 5\n{position}+7776FU\n'** 30\n"),
+            record
+        );
+    }
 }
@@ -0,0 +1,445 @@
+use indicatif::ProgressBar;
+use shogi::{Color, MoveRecord, Piece, PieceType, Position, Square};
+use std::mem;
+use std::time::Duration;
+
+use crate::environment::Event;
+use crate::game::{Game, GameOverReason};
+use crate::json::JsonValue;
+use crate::stats::MatchStatistics;
+
+use super::csa::convert_pt;
+use super::kif::{format_datetime, handicap_name, move_destination, player_names, Termination};
+use super::{now_local, RecordWriter, Reporter};
+
+const PRESETS: [(&str, &str); 11] = [
+    ("平手", "HIRATE"),
+    ("香落ち", "KY"),
+    ("右香落ち", "KY_R"),
+    ("角落ち", "KA"),
+    ("飛車落ち", "HI"),
+    ("飛香落ち", "HIKY"),
+    ("二枚落ち", "2"),
+    ("四枚落ち", "4"),
+    ("六枚落ち", "6"),
+    ("八枚落ち", "8"),
+    ("十枚落ち", "10"),
+];
+
+const HAND_PIECES: [PieceType; 7] = [
+    PieceType::Pawn,
+    PieceType::Lance,
+    PieceType::Knight,
+    PieceType::Silver,
+    PieceType::Gold,
+    PieceType::Bishop,
+    PieceType::Rook,
+];
+
+fn kind(pt: PieceType) -> JsonValue {
+    convert_pt(pt).to_string().into()
+}
+
+fn color(c: Color) -> JsonValue {
+    JsonValue::Integer(if c == Color::Black { 0 } else { 1 })
+}
+
+fn square(sq: Square) -> JsonValue {
+    JsonValue::object()
+        .field("x", i32::from(sq.file()) + 1)
+        .field("y", i32::from(sq.rank()) + 1)
+}
+
+/// Returns the initial position, which is either a preset or a whole board for custom positions.
+fn initial(pos: &Position) -> JsonValue {
+    let preset = handicap_name(pos).and_then(|name| {
+        PRESETS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, preset)| *preset)
+    });
+    if let Some(preset) = preset {
+        return JsonValue::object().field("preset", preset);
+    }
+
+    // The board is indexed by the file and then the rank, both starting from 1.
+    let board = (0..9)
+        .map(|file| {
+            JsonValue::Array(
+                (0..9)
+                    .map(
+                        |rank| match *pos.piece_at(Square::new(file, rank).unwrap()) {
+                            Some(pc) => JsonValue::object()
+                                .field("color", color(pc.color))
+                                .field("kind", kind(pc.piece_type)),
+                            None => JsonValue::object(),
+                        },
+                    )
+                    .collect(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let hands = [Color::Black, Color::White]
+        .iter()
+        .map(|&c| {
+            HAND_PIECES
+                .iter()
+                .fold(JsonValue::object(), |hand, &piece_type| {
+                    let n = pos.hand(Piece {
+                        piece_type,
+                        color: c,
+                    });
+                    hand.field(&convert_pt(piece_type).to_string(), i32::from(n))
+                })
+        })
+        .collect::<Vec<_>>();
+
+    JsonValue::object().field("preset", "OTHER").field(
+        "data",
+        JsonValue::object()
+            .field("color", color(pos.side_to_move()))
+            .field("board", board)
+            .field("hands", hands),
+    )
+}
+
+/// Returns the move in JKF, where the piece is the one before promotion.
+fn move_format(c: Color, m: &MoveRecord, last_to: Option<Square>) -> JsonValue {
+    let mut value = JsonValue::object().field("color", color(c));
+
+    match *m {
+        MoveRecord::Normal {
+            from,
+            to,
+            placed,
+            captured,
+            promoted,
+        } => {
+            let pt = if promoted {
+                placed.piece_type.unpromote().unwrap_or(placed.piece_type)
+            } else {
+                placed.piece_type
+            };
+            value = value
+                .field("from", square(from))
+                .field("to", square(to))
+                .field("piece", kind(pt));
+
+            // "promote" is written only when the piece can choose whether to promote.
+            let can_promote =
+                pt.promote().is_some() && (from.in_promotion_zone(c) || to.in_promotion_zone(c));
+            if can_promote {
+                value = value.field("promote", promoted);
+            }
+            if let Some(captured) = captured {
+                value = value.field("capture", kind(captured.piece_type));
+            }
+        }
+        MoveRecord::Drop { to, piece } => {
+            value = value
+                .field("to", square(to))
+                .field("piece", kind(piece.piece_type));
+        }
+    }
+
+    if last_to == Some(move_destination(m)) {
+        value = value.field("same", true);
+    }
+    value
+}
+
+fn time_format(elapsed: Duration, total: Duration) -> JsonValue {
+    let (e, t) = (elapsed.as_secs() as i64, total.as_secs() as i64);
+
+    JsonValue::object()
+        .field(
+            "now",
+            JsonValue::object().field("m", e / 60).field("s", e % 60),
+        )
+        .field(
+            "total",
+            JsonValue::object()
+                .field("h", t / 3600)
+                .field("m", t / 60 % 60)
+                .field("s", t % 60),
+        )
+}
+
+/// Returns the special move which terminates the game, which is the same as the termination in KIF.
+fn special(winner: Option<Color>, reason: GameOverReason, side_to_move: Color) -> &'static str {
+    match Termination::of(winner, reason, side_to_move) {
+        Termination::Resign => "TORYO",
+        Termination::TimeUp => "TIME_UP",
+        Termination::Jishogi => "JISHOGI",
+        Termination::Sennichite => "SENNICHITE",
+        Termination::Kachi => "KACHI",
+        // Illegal actions are marked with the player losing the game, e.g. "+" for Black.
+        Termination::IllegalWin | Termination::IllegalLoss => {
+            if winner == Some(Color::White) {
+                "+ILLEGAL_ACTION"
+            } else {
+                "-ILLEGAL_ACTION"
+            }
+        }
+        Termination::Chudan => "CHUDAN",
+    }
+}
+
+/// Returns the evaluation and the principal variation of the last move as comments.
+fn comments(game: &Game) -> Vec<String> {
+    let log = match game.moves.last() {
+        Some(log) => log,
+        None => return Vec::new(),
+    };
+
+    let mut comments = Vec::new();
+    if let Some(score) = log.info.score_text() {
+        comments.push(format!("score {score}"));
+    }
+    if !log.info.pv.is_empty() {
+        comments.push(format!("pv {}", log.info.pv.join(" ")));
+    }
+    comments
+}
+
+#[derive(Default)]
+pub struct JkfReporter {
+    current_bar: Option<ProgressBar>,
+    header: Vec<(String, String)>,
+    initial: Option<JsonValue>,
+    moves: Vec<JsonValue>,
+    last_to: Option<Square>,
+    side_to_move: Option<Color>,
+    black_total: Duration,
+    white_total: Duration,
+    writer: RecordWriter,
+}
+
+impl JkfReporter {
+    pub fn new(writer: RecordWriter) -> JkfReporter {
+        JkfReporter {
+            writer: writer.extension("jkf"),
+            ..Default::default()
+        }
+    }
+}
+
+impl Reporter for JkfReporter {
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        match *event {
+            Event::NewGame(ref game) => {
+                let current_game_num = stats.finished_games() + 1;
+                let num_games = stats.total_games();
+
+                self.current_bar = Some(self.writer.spinner(current_game_num, num_games));
+
+                self.writer.begin(current_game_num, game);
+                let handicap = handicap_name(&game.pos).is_some_and(|name| name != "平手");
+                let (black, white) = player_names(handicap);
+                self.header = vec![
+                    (black.to_string(), game.black_player.to_string()),
+                    (white.to_string(), game.white_player.to_string()),
+                    ("開始日時".to_string(), format_datetime(now_local())),
+                ];
+                self.initial = Some(initial(&game.pos));
+
+                // The first element represents the initial position.
+                self.moves = vec![JsonValue::object()];
+                self.last_to = None;
+                self.side_to_move = Some(game.pos.side_to_move());
+                self.black_total = Duration::from_secs(0);
+                self.white_total = Duration::from_secs(0);
+            }
+            Event::NewTurn(ref game, elapsed) => {
+                if let Some(last_move) = game.last_move() {
+                    let c = game.pos.side_to_move().flip();
+                    let total = if c == Color::Black {
+                        &mut self.black_total
+                    } else {
+                        &mut self.white_total
+                    };
+                    *total += elapsed;
+
+                    let mut value = JsonValue::object()
+                        .field("move", move_format(c, last_move, self.last_to))
+                        .field("time", time_format(elapsed, *total));
+                    let comments = comments(game);
+                    if !comments.is_empty() {
+                        value = value.field("comments", comments);
+                    }
+                    self.moves.push(value);
+                    self.last_to = Some(move_destination(last_move));
+                }
+                self.side_to_move = Some(game.pos.side_to_move());
+
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.set_message(format!("Move #{}", game.pos.ply()));
+                }
+            }
+            Event::GameOver(winner, reason) => {
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.finish_and_clear();
+                }
+
+                let stm = self.side_to_move.unwrap_or(Color::Black);
+                let mut moves = mem::take(&mut self.moves);
+                moves.push(JsonValue::object().field("special", special(winner, reason, stm)));
+
+                let mut header = mem::take(&mut self.header);
+                header.push(("終了日時".to_string(), format_datetime(now_local())));
+                let header = header
+                    .into_iter()
+                    .fold(JsonValue::object(), |h, (key, value)| h.field(&key, value));
+
+                let record = JsonValue::object()
+                    .field("header", header)
+                    .field("initial", self.initial.take().unwrap_or(JsonValue::Null))
+                    .field("moves", moves);
+                self.writer.write(&format!("{record}\n"));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::DEFAULT_SFEN;
+    use shogi::bitboard::Factory;
+    use shogi::Move;
+
+    fn position(sfen: &str) -> Position {
+        Factory::init();
+
+        let mut pos = Position::new();
+        pos.set_sfen(sfen).unwrap();
+        pos
+    }
+
+    /// Returns the move in SFEN played in the position in JKF, which follows the same square if
+    /// `same` is true.
+    fn format_move(sfen: &str, m: &str, same: bool) -> String {
+        let mut pos = position(sfen);
+        let c = pos.side_to_move();
+        pos.make_move(Move::from_sfen(m).unwrap()).unwrap();
+
+        let m = pos.move_history().last().unwrap();
+        let last_to = same.then(|| move_destination(m));
+        move_format(c, m, last_to).to_string()
+    }
+
+    #[test]
+    fn initial_preset() {
+        assert_eq!(
+            r#"{"preset":"HIRATE"}"#,
+            initial(&position(DEFAULT_SFEN)).to_string()
+        );
+        assert_eq!(
+            r#"{"preset":"KY"}"#,
+            initial(&position(
+                "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+            ))
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn initial_other() {
+        let value = initial(&position("8k/9/9/9/9/9/9/9/K8 b G2p 1")).to_string();
+
+        assert!(
+            value.starts_with(
+                r#"{"preset":"OTHER","data":{"color":0,"board":[[{"color":1,"kind":"OU"},{},"#
+            ),
+            "{value}"
+        );
+        // The last file holds the black king on 9i.
+        assert!(
+            value.contains(r#"[{},{},{},{},{},{},{},{},{"color":0,"kind":"OU"}]]"#),
+            "{value}"
+        );
+        assert!(
+            value.ends_with(
+                r#""hands":[{"FU":0,"KY":0,"KE":0,"GI":0,"KI":1,"KA":0,"HI":0},{"FU":2,"KY":0,"KE":0,"GI":0,"KI":0,"KA":0,"HI":0}]}}"#
+            ),
+            "{value}"
+        );
+    }
+
+    #[test]
+    fn moves() {
+        assert_eq!(
+            r#"{"color":0,"from":{"x":7,"y":7},"to":{"x":7,"y":6},"piece":"FU"}"#,
+            format_move(DEFAULT_SFEN, "7g7f", false)
+        );
+        assert_eq!(
+            r#"{"color":0,"to":{"x":5,"y":5},"piece":"KI"}"#,
+            format_move("k8/9/9/9/9/9/9/9/K4G3 b G 1", "G*5e", false)
+        );
+    }
+
+    #[test]
+    fn promotion() {
+        let sfen = "k8/9/9/5S3/9/9/9/9/K3+R4 b - 1";
+
+        // "promote" is written with the piece before promotion only when promotion is optional.
+        assert_eq!(
+            r#"{"color":0,"from":{"x":4,"y":4},"to":{"x":4,"y":3},"piece":"GI","promote":true}"#,
+            format_move(sfen, "4d4c+", false)
+        );
+        assert_eq!(
+            r#"{"color":0,"from":{"x":4,"y":4},"to":{"x":4,"y":3},"piece":"GI","promote":false}"#,
+            format_move(sfen, "4d4c", false)
+        );
+        assert_eq!(
+            r#"{"color":0,"from":{"x":5,"y":9},"to":{"x":5,"y":8},"piece":"RY"}"#,
+            format_move(sfen, "5i5h", false)
+        );
+    }
+
+    #[test]
+    fn capture_and_same() {
+        let sfen = "k8/9/9/9/2p6/2P6/9/9/K8 b - 1";
+        assert_eq!(
+            r#"{"color":0,"from":{"x":7,"y":6},"to":{"x":7,"y":5},"piece":"FU","capture":"FU"}"#,
+            format_move(sfen, "7f7e", false)
+        );
+        assert_eq!(
+            r#"{"color":0,"from":{"x":7,"y":6},"to":{"x":7,"y":5},"piece":"FU","capture":"FU","same":true}"#,
+            format_move(sfen, "7f7e", true)
+        );
+    }
+
+    #[test]
+    fn specials() {
+        let (b, w) = (Color::Black, Color::White);
+        assert_eq!("TORYO", special(Some(b), GameOverReason::Resign, w));
+        assert_eq!("TIME_UP", special(Some(b), GameOverReason::OutOfTime, w));
+        assert_eq!("JISHOGI", special(None, GameOverReason::MaxPly, b));
+        assert_eq!("SENNICHITE", special(None, GameOverReason::Repetition, b));
+        assert_eq!("KACHI", special(Some(b), GameOverReason::DeclareWinning, b));
+        assert_eq!(
+            "+ILLEGAL_ACTION",
+            special(Some(w), GameOverReason::DeclareWinning, b)
+        );
+        assert_eq!(
+            "-ILLEGAL_ACTION",
+            special(Some(b), GameOverReason::PerpetualCheck, w)
+        );
+        assert_eq!(
+            "+ILLEGAL_ACTION",
+            special(Some(w), GameOverReason::IllegalMove, b)
+        );
+        // White moved out of turn while Black was thinking.
+        assert_eq!(
+            "-ILLEGAL_ACTION",
+            special(Some(b), GameOverReason::IllegalMove, b)
+        );
+        assert_eq!(
+            "-ILLEGAL_ACTION",
+            special(Some(b), GameOverReason::Disconnected, w)
+        );
+        assert_eq!("CHUDAN", special(None, GameOverReason::Disconnected, w));
+    }
+}
@@ -0,0 +1,230 @@
+use ::usi::ScoreKind;
+use shogi::Color;
+use std::time::Duration;
+
+use crate::config::MatchConfig;
+use crate::engine::SearchInfo;
+use crate::environment::Event;
+use crate::game::Game;
+use crate::json::JsonValue;
+use crate::results::player_stats;
+use crate::stats::MatchStatistics;
+
+use super::{RecordWriter, Reporter};
+
+/// The version of the event schema, which is incremented on incompatible changes.
+pub const JSONL_SCHEMA_VERSION: i32 = 1;
+
+fn color(c: Color) -> &'static str {
+    if c == Color::Black {
+        "black"
+    } else {
+        "white"
+    }
+}
+
+fn millis(d: Duration) -> i64 {
+    d.as_millis() as i64
+}
+
+fn clock(game: &Game) -> JsonValue {
+    JsonValue::object()
+        .field("black", millis(game.time.black_time()))
+        .field("white", millis(game.time.white_time()))
+}
+
+/// Returns the score as `{"kind": "cp" | "mate" | "mate_sign", "value": N, "bound": null | "lower" | "upper"}`.
+fn score(info: &SearchInfo) -> JsonValue {
+    let (v, ref kind) = match info.score {
+        Some(ref score) => score,
+        None => return JsonValue::Null,
+    };
+    let (kind, bound) = match *kind {
+        ScoreKind::CpExact => ("cp", None),
+        ScoreKind::CpLowerbound => ("cp", Some("lower")),
+        ScoreKind::CpUpperbound => ("cp", Some("upper")),
+        ScoreKind::MateExact => ("mate", None),
+        ScoreKind::MateSignOnly => ("mate_sign", None),
+        ScoreKind::MateLowerbound => ("mate", Some("lower")),
+        ScoreKind::MateUpperbound => ("mate", Some("upper")),
+    };
+
+    JsonValue::object()
+        .field("kind", kind)
+        .field("value", *v)
+        .field("bound", bound)
+}
+
+/// Writes one JSON object per line for each event of the match.
+///
+/// Every object has `version` and `event` fields, followed by fields specific to the event.
+#[derive(Default)]
+pub struct JsonlReporter {
+    game_num: u32,
+    writer: RecordWriter,
+}
+
+impl JsonlReporter {
+    pub fn new(writer: RecordWriter) -> JsonlReporter {
+        JsonlReporter {
+            writer: writer.extension("jsonl"),
+            ..Default::default()
+        }
+    }
+
+    fn emit(&mut self, event: &str, fields: JsonValue) {
+        let mut line = JsonValue::object()
+            .field("version", JSONL_SCHEMA_VERSION)
+            .field("event", event);
+        if let (JsonValue::Object(ref mut line), JsonValue::Object(fields)) = (&mut line, fields) {
+            line.extend(fields);
+        }
+        self.writer.write(&format!("{line}\n"));
+    }
+}
+
+impl Reporter for JsonlReporter {
+    fn on_match_started(&mut self, config: &MatchConfig) {
+        self.emit(
+            "match_start",
+            JsonValue::object()
+                .field("usi_run", env!("CARGO_PKG_VERSION"))
+                .field("games", i64::from(config.num_games))
+                .field("black", config.black_engine.engine_path.as_str())
+                .field("white", config.white_engine.engine_path.as_str())
+                .field(
+                    "time_control",
+                    config.time_control().ok().map(|tc| tc.to_string()),
+                ),
+        );
+    }
+
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        match *event {
+            Event::NewGame(ref game) => {
+                self.game_num = stats.finished_games() + 1;
+                self.emit(
+                    "game_start",
+                    JsonValue::object()
+                        .field("game", i64::from(self.game_num))
+                        .field("black", game.black_player.as_str())
+                        .field("white", game.white_player.as_str())
+                        .field("sfen", game.pos.to_sfen())
+                        .field("clock", clock(game)),
+                );
+            }
+            Event::NewTurn(ref game, elapsed) => {
+                let (last_move, log) = match (game.last_move(), game.moves.last()) {
+                    (Some(last_move), Some(log)) => (last_move, log),
+                    _ => return,
+                };
+
+                self.emit(
+                    "move",
+                    JsonValue::object()
+                        .field("game", i64::from(self.game_num))
+                        .field("ply", i32::from(log.ply))
+                        .field("color", color(log.color))
+                        .field("move", last_move.to_sfen())
+                        .field("time", millis(elapsed))
+                        .field("clock", clock(game))
+                        .field("score", score(&log.info))
+                        .field("depth", log.info.depth)
+                        .field("seldepth", log.info.seldepth)
+                        .field("nodes", log.info.nodes.map(|n| n as i64))
+                        .field("nps", log.info.nps.map(|n| n as i64))
+                        .field("pv", log.info.pv.clone()),
+                );
+            }
+            Event::GameOver(winner, reason) => {
+                self.emit(
+                    "game_over",
+                    JsonValue::object()
+                        .field("game", i64::from(self.game_num))
+                        .field("winner", winner.map(color))
+                        .field("reason", reason.to_string()),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn on_match_finished(&mut self, stats: &MatchStatistics) {
+        self.emit(
+            "match_end",
+            JsonValue::object()
+                .field("games", i64::from(stats.finished_games()))
+                .field("black_wins", i64::from(stats.black_wins()))
+                .field("white_wins", i64::from(stats.white_wins()))
+                .field("draws", i64::from(stats.draw_games()))
+                .field(
+                    "players",
+                    JsonValue::object()
+                        .field("black", player_stats(stats, Color::Black))
+                        .field("white", player_stats(stats, Color::White)),
+                ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{config, play_game, MockEngine, Reply, TempDir};
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    /// Replaces the values of measured times, which vary between runs, with "_".
+    fn mask_times(line: &str) -> String {
+        let mut line = line.to_string();
+        for key in ["\"time\":", "\"average_time\":", "\"median_time\":"] {
+            let mut from = 0;
+            while let Some(i) = line[from..].find(key) {
+                let start = from + i + key.len();
+                let len = line[start..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(line.len() - start);
+                line.replace_range(start..start + len, "_");
+                from = start;
+            }
+        }
+        line
+    }
+
+    #[test]
+    fn events() {
+        let dir = TempDir::new("jsonl");
+        let path = dir.join("events.jsonl");
+        let reporter = Arc::new(Mutex::new(JsonlReporter::new(RecordWriter::file(
+            path.clone(),
+        ))));
+
+        let mut config = config();
+        config.black_engine.engine_path = "./b".to_string();
+        config.white_engine.engine_path = "./w".to_string();
+        reporter.lock().unwrap().on_match_started(&config);
+
+        let black = MockEngine::new("b")
+            .then(Reply::Move("7g7f"))
+            .info("depth 10 seldepth 12 nodes 5000 nps 100000 score cp 42 pv 7g7f 3c3d");
+        let result = play_game(&config, black, MockEngine::new("w"), reporter.clone()).unwrap();
+        let mut stats = MatchStatistics::new(1);
+        stats.record_game(&result);
+        reporter.lock().unwrap().on_match_finished(&stats);
+
+        let version = env!("CARGO_PKG_VERSION");
+        let expected = [
+            format!(r#"{{"version":1,"event":"match_start","usi_run":"{version}","games":1,"black":"./b","white":"./w","time_control":"0s + 5s byoyomi"}}"#),
+            r#"{"version":1,"event":"game_start","game":1,"black":"b","white":"w","sfen":"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1","clock":{"black":0,"white":0}}"#.to_string(),
+            r#"{"version":1,"event":"move","game":1,"ply":1,"color":"black","move":"7g7f","time":_,"clock":{"black":0,"white":0},"score":{"kind":"cp","value":42,"bound":null},"depth":10,"seldepth":12,"nodes":5000,"nps":100000,"pv":["7g7f","3c3d"]}"#.to_string(),
+            r#"{"version":1,"event":"game_over","game":1,"winner":"black","reason":"resign"}"#.to_string(),
+            r#"{"version":1,"event":"match_end","games":1,"black_wins":1,"white_wins":0,"draws":0,"players":{"black":{"moves":1,"average_time":_,"median_time":_,"time_losses":0,"overruns":0,"average_depth":10,"average_nps":100000,"ponder_hits":0,"ponder_misses":0},"white":{"moves":0,"average_time":_,"median_time":_,"time_losses":0,"overruns":0,"average_depth":null,"average_nps":null,"ponder_hits":0,"ponder_misses":0}}}"#.to_string(),
+        ];
+        let lines = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            expected.to_vec(),
+            lines.lines().map(mask_times).collect::<Vec<_>>()
+        );
+    }
+}
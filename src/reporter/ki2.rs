@@ -0,0 +1,352 @@
+use indicatif::ProgressBar;
+use shogi::{Color, Move, MoveRecord, Piece, PieceType, Position, Square};
+
+use crate::environment::Event;
+use crate::stats::MatchStatistics;
+
+use super::kif::{
+    format_datetime, header, move_destination, pad, piece_name, result_line, square_name,
+};
+use super::{now_local, RecordWriter, Reporter};
+
+const MOVES_PER_LINE: usize = 6;
+
+/// Represents how a piece moves as seen from the player moving it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Direction {
+    Up,
+    Down,
+    Sideways,
+}
+
+impl Direction {
+    fn of(c: Color, from: Square, to: Square) -> Direction {
+        let forward = if c == Color::Black {
+            from.rank() as i8 - to.rank() as i8
+        } else {
+            to.rank() as i8 - from.rank() as i8
+        };
+
+        match forward {
+            0 => Direction::Sideways,
+            d if d > 0 => Direction::Up,
+            _ => Direction::Down,
+        }
+    }
+
+    fn notation(self) -> &'static str {
+        match self {
+            Direction::Up => "上",
+            Direction::Down => "引",
+            Direction::Sideways => "寄",
+        }
+    }
+}
+
+/// Returns the file of the given square counted from the right side of the given player.
+fn relative_file(c: Color, sq: Square) -> u8 {
+    if c == Color::Black {
+        sq.file()
+    } else {
+        8 - sq.file()
+    }
+}
+
+/// Returns whether the piece at `from` can legally move to `to`, e.g. it is not pinned.
+fn is_legal(pos: &Position, from: Square, to: Square) -> bool {
+    // The move is tried on a copy of the position, with or without promotion.
+    let sfen = pos.to_sfen();
+    [false, true].into_iter().any(|promote| {
+        let mut pos = Position::new();
+        pos.set_sfen(&sfen).is_ok() && pos.make_move(Move::Normal { from, to, promote }).is_ok()
+    })
+}
+
+/// Returns squares of other pieces of the same kind which can legally move to the given square.
+fn other_candidates(pos: &Position, pc: Piece, from: Option<Square>, to: Square) -> Vec<Square> {
+    Square::iter()
+        .filter(|&sq| Some(sq) != from && *pos.piece_at(sq) == Some(pc))
+        .filter(|&sq| (&pos.move_candidates(sq, pc) & to).is_any())
+        .filter(|&sq| is_legal(pos, sq, to))
+        .collect()
+}
+
+/// Returns the relative movement which tells the moved piece apart from the other candidates.
+fn disambiguation(pc: Piece, from: Square, to: Square, others: &[Square]) -> String {
+    if others.is_empty() {
+        return String::new();
+    }
+
+    let c = pc.color;
+    let dir = Direction::of(c, from, to);
+    let same_dir = others
+        .iter()
+        .filter(|&&sq| Direction::of(c, sq, to) == dir)
+        .collect::<Vec<_>>();
+    if same_dir.is_empty() {
+        return dir.notation().to_string();
+    }
+
+    // 直 is not used for dragons and horses, which are told apart only by their positions.
+    let is_ranging = matches!(pc.piece_type, PieceType::ProRook | PieceType::ProBishop);
+    if !is_ranging && dir == Direction::Up && from.file() == to.file() {
+        return "直".to_string();
+    }
+
+    let file = relative_file(c, from);
+    let position = if same_dir.iter().all(|&&sq| relative_file(c, sq) > file) {
+        "右"
+    } else if same_dir.iter().all(|&&sq| relative_file(c, sq) < file) {
+        "左"
+    } else {
+        return dir.notation().to_string();
+    };
+
+    // The movement is added only when the position alone is ambiguous among all candidates.
+    let unique = others.iter().all(|&sq| {
+        let f = relative_file(c, sq);
+        if position == "右" {
+            f > file
+        } else {
+            f < file
+        }
+    });
+    if unique || is_ranging {
+        position.to_string()
+    } else {
+        format!("{position}{}", dir.notation())
+    }
+}
+
+/// Returns the KI2 notation of the given move in the position before the move, e.g. "７七銀右".
+fn move_notation(pos: &Position, m: &MoveRecord, last_to: Option<Square>) -> String {
+    let dest = |to: Square| {
+        if last_to == Some(to) {
+            "同　".to_string()
+        } else {
+            square_name(to)
+        }
+    };
+
+    match *m {
+        MoveRecord::Normal {
+            from, to, promoted, ..
+        } => {
+            let pc = match *pos.piece_at(from) {
+                Some(pc) => pc,
+                None => return String::new(),
+            };
+            let others = other_candidates(pos, pc, Some(from), to);
+
+            let can_promote = pc.piece_type.promote().is_some()
+                && (from.in_promotion_zone(pc.color) || to.in_promotion_zone(pc.color));
+            let promotion = match (promoted, can_promote) {
+                (true, _) => "成",
+                (false, true) => "不成",
+                (false, false) => "",
+            };
+
+            format!(
+                "{}{}{}{promotion}",
+                dest(to),
+                piece_name(pc.piece_type),
+                disambiguation(pc, from, to, &others)
+            )
+        }
+        MoveRecord::Drop { to, piece } => {
+            // 打 is written only when a piece on the board can move to the same square.
+            let drop = if other_candidates(pos, piece, None, to).is_empty() {
+                ""
+            } else {
+                "打"
+            };
+            format!("{}{}{drop}", dest(to), piece_name(piece.piece_type))
+        }
+    }
+}
+
+pub struct Ki2Reporter {
+    current_bar: Option<ProgressBar>,
+    header: Vec<String>,
+    moves: Vec<String>,
+    handicap: bool,
+    pos: Position,
+    last_to: Option<Square>,
+    writer: RecordWriter,
+}
+
+impl Ki2Reporter {
+    pub fn new(writer: RecordWriter) -> Ki2Reporter {
+        Ki2Reporter {
+            writer: writer.extension("ki2").separator("\n"),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Ki2Reporter {
+    fn default() -> Ki2Reporter {
+        Ki2Reporter {
+            current_bar: None,
+            header: Vec::new(),
+            moves: Vec::new(),
+            handicap: false,
+            pos: Position::new(),
+            last_to: None,
+            writer: RecordWriter::default(),
+        }
+    }
+}
+
+impl Reporter for Ki2Reporter {
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        match *event {
+            Event::NewGame(ref game) => {
+                let current_game_num = stats.finished_games() + 1;
+                let num_games = stats.total_games();
+
+                self.current_bar = Some(self.writer.spinner(current_game_num, num_games));
+
+                self.writer.begin(current_game_num, game);
+                (self.header, self.handicap) = header(game);
+
+                // The position is replayed separately as notations depend on the position before each move.
+                self.pos = Position::new();
+                let _ = self.pos.set_sfen(&game.pos.to_sfen());
+                self.moves.clear();
+                self.last_to = None;
+            }
+            Event::NewTurn(ref game, _) => {
+                if let Some(last_move) = game.last_move() {
+                    let mark = if self.pos.side_to_move() == Color::Black {
+                        "▲"
+                    } else {
+                        "△"
+                    };
+                    self.moves.push(format!(
+                        "{mark}{}",
+                        move_notation(&self.pos, last_move, self.last_to)
+                    ));
+                    self.last_to = Some(move_destination(last_move));
+
+                    if let Some(m) = Move::from_sfen(&last_move.to_sfen()) {
+                        let _ = self.pos.make_move(m);
+                    }
+                }
+
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.set_message(format!("Move #{}", game.pos.ply()));
+                }
+            }
+            Event::GameOver(winner, reason) => {
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.finish_and_clear();
+                }
+
+                let mut lines = self.header.clone();
+                lines.push(format!("終了日時：{}", format_datetime(now_local())));
+                for chunk in self.moves.chunks(MOVES_PER_LINE) {
+                    let line = chunk.iter().map(|m| pad(m, 12)).collect::<String>();
+                    lines.push(line.trim_end().to_string());
+                }
+                lines.push(result_line(
+                    winner,
+                    reason,
+                    self.pos.side_to_move(),
+                    self.moves.len(),
+                    self.handicap,
+                ));
+
+                self.writer.write(&format!("{}\n", lines.join("\n")));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shogi::bitboard::Factory;
+
+    /// Returns the notation of the move in SFEN in the position.
+    fn notation(sfen: &str, m: &str) -> String {
+        Factory::init();
+
+        let mut pos = Position::new();
+        pos.set_sfen(sfen).unwrap();
+        let mut after = Position::new();
+        after.set_sfen(sfen).unwrap();
+        after.make_move(Move::from_sfen(m).unwrap()).unwrap();
+
+        move_notation(&pos, after.move_history().last().unwrap(), None)
+    }
+
+    #[test]
+    fn movement() {
+        let sfen = "8k/9/9/9/9/9/9/5G3/K2G5 b - 1";
+        assert_eq!("５八金上", notation(sfen, "6i5h"));
+        assert_eq!("５八金寄", notation(sfen, "4h5h"));
+
+        let sfen = "8k/9/9/9/9/9/5S3/9/K2S5 b - 1";
+        assert_eq!("５八銀上", notation(sfen, "6i5h"));
+        assert_eq!("５八銀引", notation(sfen, "4g5h"));
+    }
+
+    #[test]
+    fn position() {
+        let sfen = "8k/9/9/9/9/9/9/9/K2G1G3 b - 1";
+        assert_eq!("５八金右", notation(sfen, "4i5h"));
+        assert_eq!("５八金左", notation(sfen, "6i5h"));
+
+        // Right and left are seen from the player moving the piece.
+        let sfen = "3g1g2k/9/9/9/9/9/9/9/K8 w - 1";
+        assert_eq!("５二金右", notation(sfen, "6a5b"));
+        assert_eq!("５二金左", notation(sfen, "4a5b"));
+
+        let sfen = "8k/9/9/9/9/9/9/9/K2GG4 b - 1";
+        assert_eq!("５八金直", notation(sfen, "5i5h"));
+        assert_eq!("５八金左", notation(sfen, "6i5h"));
+    }
+
+    #[test]
+    fn position_and_movement() {
+        let sfen = "8k/9/9/9/9/9/9/5G3/K2G1G3 b - 1";
+        assert_eq!("５八金右上", notation(sfen, "4i5h"));
+        assert_eq!("５八金寄", notation(sfen, "4h5h"));
+        assert_eq!("５八金左", notation(sfen, "6i5h"));
+    }
+
+    #[test]
+    fn dragon_and_horse() {
+        let sfen = "8k/9/9/9/9/9/9/9/K3+R+R3 b - 1";
+        assert_eq!("５八龍左", notation(sfen, "5i5h"));
+        assert_eq!("５八龍右", notation(sfen, "4i5h"));
+
+        let sfen = "8k/9/9/9/9/9/9/9/K3+B+B3 b - 1";
+        assert_eq!("５八馬左", notation(sfen, "5i5h"));
+        assert_eq!("５八馬右", notation(sfen, "4i5h"));
+    }
+
+    #[test]
+    fn drop() {
+        let sfen = "8k/9/9/9/9/9/9/9/K4G3 b G 1";
+        assert_eq!("５八金打", notation(sfen, "G*5h"));
+        assert_eq!("５五金", notation(sfen, "G*5e"));
+    }
+
+    #[test]
+    fn promotion() {
+        let sfen = "8k/9/9/5S3/9/9/9/9/K8 b - 1";
+        assert_eq!("４三銀成", notation(sfen, "4d4c+"));
+        assert_eq!("４三銀不成", notation(sfen, "4d4c"));
+        assert_eq!("３五銀", notation(sfen, "4d3e"));
+    }
+
+    #[test]
+    fn pinned_piece() {
+        // The gold on 4i can not move as it is pinned by the rook on 1i.
+        let sfen = "k8/9/9/9/9/9/9/9/3GKG2r b - 1";
+        assert_eq!("５八金", notation(sfen, "6i5h"));
+    }
+}
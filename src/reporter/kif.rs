@@ -0,0 +1,630 @@
+use indicatif::ProgressBar;
+use shogi::{Color, MoveRecord, Piece, PieceType, Position, Square};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+use crate::environment::Event;
+use crate::game::{Game, GameOverReason};
+use crate::stats::MatchStatistics;
+
+use super::{now_local, RecordWriter, Reporter};
+
+const HANDICAPS: [(&str, &str); 11] = [
+    (
+        "平手",
+        "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -",
+    ),
+    (
+        "香落ち",
+        "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "右香落ち",
+        "1nsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "角落ち",
+        "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "飛車落ち",
+        "lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "飛香落ち",
+        "lnsgkgsn1/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "二枚落ち",
+        "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "四枚落ち",
+        "1nsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "六枚落ち",
+        "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "八枚落ち",
+        "3gkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+    (
+        "十枚落ち",
+        "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w -",
+    ),
+];
+
+const FILES: [&str; 9] = ["１", "２", "３", "４", "５", "６", "７", "８", "９"];
+const RANKS: [&str; 9] = ["一", "二", "三", "四", "五", "六", "七", "八", "九"];
+const HAND_PIECES: [PieceType; 7] = [
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Gold,
+    PieceType::Silver,
+    PieceType::Knight,
+    PieceType::Lance,
+    PieceType::Pawn,
+];
+
+/// Returns the name of the given piece type used in move notations.
+pub(super) fn piece_name(pt: PieceType) -> &'static str {
+    match pt {
+        PieceType::Pawn => "歩",
+        PieceType::Lance => "香",
+        PieceType::Knight => "桂",
+        PieceType::Silver => "銀",
+        PieceType::Gold => "金",
+        PieceType::King => "玉",
+        PieceType::Rook => "飛",
+        PieceType::Bishop => "角",
+        PieceType::ProPawn => "と",
+        PieceType::ProLance => "成香",
+        PieceType::ProKnight => "成桂",
+        PieceType::ProSilver => "成銀",
+        PieceType::ProBishop => "馬",
+        PieceType::ProRook => "龍",
+    }
+}
+
+/// Returns the one-letter name of the given piece type used in board diagrams.
+fn board_piece_name(pt: PieceType) -> &'static str {
+    match pt {
+        PieceType::ProLance => "杏",
+        PieceType::ProKnight => "圭",
+        PieceType::ProSilver => "全",
+        _ => piece_name(pt),
+    }
+}
+
+/// Returns the name of the given square, e.g. "７六".
+pub(super) fn square_name(sq: Square) -> String {
+    format!("{}{}", FILES[sq.file() as usize], RANKS[sq.rank() as usize])
+}
+
+/// Returns the kanji numeral of the given number from 1 to 18.
+fn kanji_number(n: u8) -> String {
+    match n {
+        1..=9 => RANKS[(n - 1) as usize].to_string(),
+        10 => "十".to_string(),
+        _ => format!("十{}", RANKS[(n - 11) as usize]),
+    }
+}
+
+/// Returns the name of the handicap if the given position is one of the standard handicaps.
+pub(super) fn handicap_name(pos: &Position) -> Option<&'static str> {
+    let sfen = pos.to_sfen();
+    let key = sfen.split(' ').take(3).collect::<Vec<_>>().join(" ");
+
+    HANDICAPS
+        .iter()
+        .find(|(_, handicap)| *handicap == key)
+        .map(|(name, _)| *name)
+}
+
+/// Returns the names of both players, which are 上手/下手 in handicap games.
+pub(super) fn player_names(handicap: bool) -> (&'static str, &'static str) {
+    if handicap {
+        ("下手", "上手")
+    } else {
+        ("先手", "後手")
+    }
+}
+
+fn hand_pieces(pos: &Position, c: Color) -> String {
+    let pieces = HAND_PIECES
+        .iter()
+        .filter_map(|&piece_type| {
+            let n = pos.hand(Piece {
+                piece_type,
+                color: c,
+            });
+            match n {
+                0 => None,
+                1 => Some(piece_name(piece_type).to_string()),
+                _ => Some(format!("{}{}", piece_name(piece_type), kanji_number(n))),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if pieces.is_empty() {
+        "なし".to_string()
+    } else {
+        pieces.join("　")
+    }
+}
+
+/// Returns the board diagram (BOD) of the given position.
+pub(super) fn board_diagram(pos: &Position) -> Vec<String> {
+    let mut lines = vec![
+        format!("後手の持駒：{}", hand_pieces(pos, Color::White)),
+        "  ９ ８ ７ ６ ５ ４ ３ ２ １".to_string(),
+        "+---------------------------+".to_string(),
+    ];
+
+    for rank in 0..9 {
+        let mut line = "|".to_string();
+        for file in (0..9).rev() {
+            let sq = Square::new(file, rank).unwrap();
+            match *pos.piece_at(sq) {
+                Some(pc) => {
+                    line.push(if pc.color == Color::Black { ' ' } else { 'v' });
+                    line.push_str(board_piece_name(pc.piece_type));
+                }
+                None => line.push_str(" ・"),
+            }
+        }
+        line.push('|');
+        line.push_str(RANKS[rank as usize]);
+        lines.push(line);
+    }
+
+    lines.push("+---------------------------+".to_string());
+    lines.push(format!("先手の持駒：{}", hand_pieces(pos, Color::Black)));
+    if pos.side_to_move() == Color::White {
+        lines.push("後手番".to_string());
+    }
+    lines
+}
+
+/// Returns the KIF notation of the given move, e.g. "７六歩(77)" or "同　銀成(67)".
+fn move_notation(m: &MoveRecord, last_to: Option<Square>) -> String {
+    match *m {
+        MoveRecord::Normal {
+            from,
+            to,
+            placed,
+            promoted,
+            ..
+        } => {
+            let dest = if last_to == Some(to) {
+                "同　".to_string()
+            } else {
+                square_name(to)
+            };
+            format!(
+                "{dest}{}{}({}{})",
+                // The placed piece is already promoted by the move.
+                piece_name(if promoted {
+                    placed.piece_type.unpromote().unwrap_or(placed.piece_type)
+                } else {
+                    placed.piece_type
+                }),
+                if promoted { "成" } else { "" },
+                from.file() + 1,
+                from.rank() + 1
+            )
+        }
+        MoveRecord::Drop { to, piece } => {
+            format!("{}{}打", square_name(to), piece_name(piece.piece_type))
+        }
+    }
+}
+
+pub(super) fn move_destination(m: &MoveRecord) -> Square {
+    match *m {
+        MoveRecord::Normal { to, .. } | MoveRecord::Drop { to, .. } => to,
+    }
+}
+
+/// The special move which terminates the game, shared by KIF and JKF records.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) enum Termination {
+    Resign,
+    TimeUp,
+    /// Games reaching the maximum number of moves are recorded as impasses, as KIF has no draw.
+    Jishogi,
+    Sennichite,
+    Kachi,
+    /// The player to move wins by an illegal action of the opponent, e.g. moving out of turn.
+    IllegalWin,
+    IllegalLoss,
+    /// The game ended without any winner for a reason other than a draw.
+    Chudan,
+}
+
+impl Termination {
+    pub(super) fn of(winner: Option<Color>, reason: GameOverReason, side_to_move: Color) -> Self {
+        match reason {
+            GameOverReason::Resign => Termination::Resign,
+            GameOverReason::OutOfTime => Termination::TimeUp,
+            GameOverReason::MaxPly => Termination::Jishogi,
+            GameOverReason::Repetition => Termination::Sennichite,
+            GameOverReason::DeclareWinning if winner == Some(side_to_move) => Termination::Kachi,
+            GameOverReason::IllegalMove
+            | GameOverReason::PerpetualCheck
+            | GameOverReason::Disconnected
+            | GameOverReason::DeclareWinning => match winner {
+                Some(c) if c == side_to_move => Termination::IllegalWin,
+                Some(_) => Termination::IllegalLoss,
+                None => Termination::Chudan,
+            },
+        }
+    }
+}
+
+/// Returns the special move which terminates the game, e.g. "投了".
+pub(super) fn termination(
+    winner: Option<Color>,
+    reason: GameOverReason,
+    side_to_move: Color,
+) -> &'static str {
+    match Termination::of(winner, reason, side_to_move) {
+        Termination::Resign => "投了",
+        Termination::TimeUp => "切れ負け",
+        Termination::Jishogi => "持将棋",
+        Termination::Sennichite => "千日手",
+        Termination::Kachi => "入玉勝ち",
+        Termination::IllegalWin => "反則勝ち",
+        Termination::IllegalLoss => "反則負け",
+        Termination::Chudan => "中断",
+    }
+}
+
+/// Returns the summary line of the game, e.g. "まで76手で先手の勝ち".
+pub(super) fn result_line(
+    winner: Option<Color>,
+    reason: GameOverReason,
+    side_to_move: Color,
+    num_moves: usize,
+    handicap: bool,
+) -> String {
+    let (black, white) = player_names(handicap);
+    let name = |c: Color| if c == Color::Black { black } else { white };
+
+    match (winner, reason) {
+        (None, GameOverReason::Repetition) => format!("まで{num_moves}手で千日手"),
+        (None, GameOverReason::MaxPly) => format!("まで{num_moves}手で持将棋"),
+        (None, _) => format!("まで{num_moves}手で中断"),
+        (Some(c), GameOverReason::OutOfTime) => {
+            format!("まで{num_moves}手で時間切れにより{}の勝ち", name(c))
+        }
+        (
+            Some(c),
+            GameOverReason::IllegalMove
+            | GameOverReason::PerpetualCheck
+            | GameOverReason::Disconnected,
+        ) => {
+            format!("まで{num_moves}手で{}の反則勝ち", name(c))
+        }
+        (Some(c), GameOverReason::DeclareWinning) if c == side_to_move => {
+            format!("まで{num_moves}手で入玉宣言により{}の勝ち", name(c))
+        }
+        // The declaration was not satisfied, which loses the game for the player who declared.
+        (Some(c), GameOverReason::DeclareWinning) => {
+            format!("まで{num_moves}手で{}の反則負け", name(c.flip()))
+        }
+        (Some(c), _) => format!("まで{num_moves}手で{}の勝ち", name(c)),
+    }
+}
+
+pub(super) fn format_datetime(t: OffsetDateTime) -> String {
+    format!(
+        "{:04}/{:02}/{:02} {:02}:{:02}:{:02}",
+        t.year(),
+        t.month() as u8,
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+/// Returns the header lines of the game shared by KIF and KI2, along with whether it is a handicap game.
+pub(super) fn header(game: &Game) -> (Vec<String>, bool) {
+    let handicap = handicap_name(&game.pos);
+    let is_handicap = handicap.is_some_and(|name| name != "平手");
+    let (black, white) = player_names(is_handicap);
+
+    // The encoding is declared as shogi software reads KIF files in Shift_JIS by default.
+    let mut lines = vec![
+        "#KIF version=2.0 encoding=UTF-8".to_string(),
+        "# ---- usi-run 棋譜ファイル ----".to_string(),
+        format!("開始日時：{}", format_datetime(now_local())),
+    ];
+    match handicap {
+        Some(name) => lines.push(format!("手合割：{name}")),
+        None => lines.extend(board_diagram(&game.pos)),
+    }
+    lines.push(format!("{black}：{}", game.black_player));
+    lines.push(format!("{white}：{}", game.white_player));
+
+    (lines, is_handicap)
+}
+
+/// Pads the given notation to the column width of KIF move lines, counting full-width letters twice.
+pub(super) fn pad(s: &str, width: usize) -> String {
+    let len = s
+        .chars()
+        .map(|c| if c.is_ascii() { 1 } else { 2 })
+        .sum::<usize>();
+    format!("{s}{}", " ".repeat(width.saturating_sub(len)))
+}
+
+fn format_time(elapsed: Duration, total: Duration) -> String {
+    let (e, t) = (elapsed.as_secs(), total.as_secs());
+    format!(
+        "({:2}:{:02}/{:02}:{:02}:{:02})",
+        e / 60,
+        e % 60,
+        t / 3600,
+        t / 60 % 60,
+        t % 60
+    )
+}
+
+#[derive(Default)]
+pub struct KifReporter {
+    current_bar: Option<ProgressBar>,
+    header: Vec<String>,
+    moves: Vec<String>,
+    handicap: bool,
+    last_to: Option<Square>,
+    side_to_move: Option<Color>,
+    black_total: Duration,
+    white_total: Duration,
+    writer: RecordWriter,
+}
+
+impl KifReporter {
+    pub fn new(writer: RecordWriter) -> KifReporter {
+        KifReporter {
+            writer: writer.extension("kif").separator("\n"),
+            ..Default::default()
+        }
+    }
+}
+
+impl Reporter for KifReporter {
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        match *event {
+            Event::NewGame(ref game) => {
+                let current_game_num = stats.finished_games() + 1;
+                let num_games = stats.total_games();
+
+                self.current_bar = Some(self.writer.spinner(current_game_num, num_games));
+
+                self.writer.begin(current_game_num, game);
+                (self.header, self.handicap) = header(game);
+
+                self.moves.clear();
+                self.last_to = None;
+                self.side_to_move = Some(game.pos.side_to_move());
+                self.black_total = Duration::from_secs(0);
+                self.white_total = Duration::from_secs(0);
+            }
+            Event::NewTurn(ref game, elapsed) => {
+                if let Some(last_move) = game.last_move() {
+                    let total = if game.pos.side_to_move() == Color::White {
+                        &mut self.black_total
+                    } else {
+                        &mut self.white_total
+                    };
+                    *total += elapsed;
+
+                    self.moves.push(format!(
+                        "{:4} {} {}",
+                        self.moves.len() + 1,
+                        pad(&move_notation(last_move, self.last_to), 12),
+                        format_time(elapsed, *total)
+                    ));
+                    self.last_to = Some(move_destination(last_move));
+                }
+                self.side_to_move = Some(game.pos.side_to_move());
+
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.set_message(format!("Move #{}", game.pos.ply()));
+                }
+            }
+            Event::GameOver(winner, reason) => {
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.finish_and_clear();
+                }
+
+                let stm = self.side_to_move.unwrap_or(Color::Black);
+                let mut lines = self.header.clone();
+                lines.push(format!("終了日時：{}", format_datetime(now_local())));
+                lines.push("手数----指手---------消費時間--".to_string());
+                lines.extend(self.moves.iter().cloned());
+                lines.push(format!(
+                    "{:4} {}",
+                    self.moves.len() + 1,
+                    termination(winner, reason, stm)
+                ));
+                lines.push(result_line(
+                    winner,
+                    reason,
+                    stm,
+                    self.moves.len(),
+                    self.handicap,
+                ));
+
+                self.writer.write(&format!("{}\n", lines.join("\n")));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::DEFAULT_SFEN;
+    use shogi::bitboard::Factory;
+    use shogi::Move;
+
+    fn position(sfen: &str) -> Position {
+        Factory::init();
+
+        let mut pos = Position::new();
+        pos.set_sfen(sfen).unwrap();
+        pos
+    }
+
+    #[test]
+    fn notation() {
+        let mut pos = position(DEFAULT_SFEN);
+        let mut notations = Vec::new();
+        let mut last_to = None;
+        for m in ["7g7f", "3c3d", "8h2b+", "3a2b", "B*4e"] {
+            pos.make_move(Move::from_sfen(m).unwrap()).unwrap();
+            let record = pos.move_history().last().unwrap();
+            notations.push(move_notation(record, last_to));
+            last_to = Some(move_destination(record));
+        }
+
+        assert_eq!(
+            vec![
+                "７六歩(77)",
+                "３四歩(33)",
+                "２二角成(88)",
+                "同　銀(31)",
+                "４五角打"
+            ],
+            notations
+        );
+    }
+
+    #[test]
+    fn handicap() {
+        assert_eq!(Some("平手"), handicap_name(&position(DEFAULT_SFEN)));
+        assert_eq!(
+            Some("角落ち"),
+            handicap_name(&position(
+                "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+            ))
+        );
+
+        assert_eq!(
+            None,
+            handicap_name(&position(
+                "lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2"
+            ))
+        );
+    }
+
+    #[test]
+    fn termination_moves() {
+        let (b, w) = (Some(Color::Black), Some(Color::White));
+        assert_eq!("投了", termination(b, GameOverReason::Resign, Color::White));
+        assert_eq!(
+            "切れ負け",
+            termination(w, GameOverReason::OutOfTime, Color::Black)
+        );
+        assert_eq!(
+            "千日手",
+            termination(None, GameOverReason::Repetition, Color::Black)
+        );
+        assert_eq!(
+            "反則負け",
+            termination(w, GameOverReason::IllegalMove, Color::Black)
+        );
+        // The player to move wins when the opponent has moved out of turn.
+        assert_eq!(
+            "反則勝ち",
+            termination(b, GameOverReason::IllegalMove, Color::Black)
+        );
+        assert_eq!(
+            "入玉勝ち",
+            termination(b, GameOverReason::DeclareWinning, Color::Black)
+        );
+        assert_eq!(
+            "持将棋",
+            termination(None, GameOverReason::MaxPly, Color::Black)
+        );
+        assert_eq!(
+            "中断",
+            termination(None, GameOverReason::Disconnected, Color::Black)
+        );
+    }
+
+    #[test]
+    fn result_lines() {
+        let (b, w) = (Some(Color::Black), Some(Color::White));
+        assert_eq!(
+            "まで76手で先手の勝ち",
+            result_line(b, GameOverReason::Resign, Color::White, 76, false)
+        );
+        assert_eq!(
+            "まで10手で時間切れにより上手の勝ち",
+            result_line(w, GameOverReason::OutOfTime, Color::Black, 10, true)
+        );
+        assert_eq!(
+            "まで12手で千日手",
+            result_line(None, GameOverReason::Repetition, Color::Black, 12, false)
+        );
+        assert_eq!(
+            "まで256手で持将棋",
+            result_line(None, GameOverReason::MaxPly, Color::Black, 256, false)
+        );
+        assert_eq!(
+            "まで3手で後手の反則勝ち",
+            result_line(w, GameOverReason::PerpetualCheck, Color::White, 3, false)
+        );
+    }
+
+    #[test]
+    fn declaration() {
+        let (b, w) = (Some(Color::Black), Some(Color::White));
+        assert_eq!(
+            "まで100手で入玉宣言により先手の勝ち",
+            result_line(b, GameOverReason::DeclareWinning, Color::Black, 100, false)
+        );
+        // The player who declared illegally loses the game.
+        assert_eq!(
+            "まで100手で先手の反則負け",
+            result_line(w, GameOverReason::DeclareWinning, Color::Black, 100, false)
+        );
+        assert_eq!(
+            "反則負け",
+            termination(w, GameOverReason::DeclareWinning, Color::Black)
+        );
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(
+            "( 0:00/00:00:00)",
+            format_time(Duration::from_millis(999), Duration::from_millis(999))
+        );
+        assert_eq!(
+            "( 1:05/01:02:05)",
+            format_time(Duration::from_secs(65), Duration::from_secs(3725))
+        );
+    }
+
+    #[test]
+    fn diagram() {
+        let lines = board_diagram(&position("8k/9/9/9/4+s4/9/9/9/K8 w 2Pg 1"));
+
+        assert_eq!(15, lines.len());
+        assert_eq!("後手の持駒：金", lines[0]);
+        assert_eq!("  ９ ８ ７ ６ ５ ４ ３ ２ １", lines[1]);
+        assert_eq!("| ・ ・ ・ ・ ・ ・ ・ ・v玉|一", lines[3]);
+        assert_eq!("| ・ ・ ・ ・v全 ・ ・ ・ ・|五", lines[7]);
+        assert_eq!("| 玉 ・ ・ ・ ・ ・ ・ ・ ・|九", lines[11]);
+        assert_eq!("先手の持駒：歩二", lines[13]);
+        assert_eq!("後手番", lines[14]);
+    }
+}
@@ -1,30 +1,125 @@
+use crate::config::MatchConfig;
 use crate::environment::Event;
-use crate::stats::MatchStatistics;
+use crate::stats::{GamePhase, MatchStatistics};
 use ::usi::{EngineOutput, GuiCommand};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use shogi::Color;
 
 pub trait Reporter {
+    fn on_match_started(&mut self, _config: &MatchConfig) {}
     fn on_send_command(&mut self, _stm: Color, _command: &GuiCommand, _arg: &str) {}
     fn on_receive_command(&mut self, _stm: Color, _output: &EngineOutput) {}
+    fn on_engine_stderr(&mut self, _stm: Color, _line: &str) {}
     fn on_game_event(&mut self, _event: &Event, _stats: &MatchStatistics) {}
-    fn on_match_finished(&mut self, stats: &MatchStatistics) {
-        println!("Total\tBlack\tWhite\tDraw");
+    fn on_match_finished(&mut self, _stats: &MatchStatistics) {}
+}
+
+/// Returns the spinner showing the progress of the given game, e.g. "[1/10] | Move #42".
+pub fn spinner(game_num: u32, num_games: u32, target: ProgressDrawTarget) -> ProgressBar {
+    let pbar = ProgressBar::new_spinner();
+    pbar.set_draw_target(target);
+    pbar.set_style(
+        ProgressStyle::default_spinner()
+            .tick_chars("|/-\\ ")
+            .template("{prefix:.bold.dim} {spinner} {msg}")
+            .unwrap(),
+    );
+    pbar.set_prefix(format!("[{game_num}/{num_games}]"));
+    pbar.set_message("Starting...");
+    pbar
+}
+
+/// Prints the summary of the match in tab-separated tables, which is shown by the displays of
+/// the progress rather than game records.
+pub fn print_summary(stats: &MatchStatistics) {
+    println!("Total\tBlack\tWhite\tDraw");
+    println!(
+        "{}\t{}\t{}\t{}",
+        stats.finished_games(),
+        stats.black_wins(),
+        stats.white_wins(),
+        stats.draw_games()
+    );
+
+    println!("Time(ms)\tMoves\tAvg\tMedian\tOpening\tMiddle\tEndgame\tRemain\tTimeLoss");
+    for (name, c) in [("Black", Color::Black), ("White", Color::White)] {
+        let time = stats.time(c);
+        let phases = GamePhase::ALL
+            .iter()
+            .map(|phase| time.phase_average(*phase).as_millis().to_string())
+            .collect::<Vec<_>>();
         println!(
-            "{}\t{}\t{}\t{}",
-            stats.finished_games(),
-            stats.black_wins(),
-            stats.white_wins(),
-            stats.draw_games()
+            "{name}\t{}\t{}\t{}\t{}\t{}\t{}",
+            time.moves(),
+            time.average().as_millis(),
+            time.median().as_millis(),
+            phases.join("\t"),
+            time.average_remaining().as_millis(),
+            time.time_losses()
         );
     }
+
+    let (black, white) = (stats.search(Color::Black), stats.search(Color::White));
+    if black.average_depth().is_some() || white.average_depth().is_some() {
+        println!("Search\tDepth\tNPS");
+        for (name, search) in [("Black", black), ("White", white)] {
+            println!(
+                "{name}\t{}\t{}",
+                search
+                    .average_depth()
+                    .map_or("-".to_string(), |d| format!("{d:.1}")),
+                search
+                    .average_nps()
+                    .map_or("-".to_string(), |n| n.to_string())
+            );
+        }
+    }
+
+    let (black, white) = (stats.overruns(Color::Black), stats.overruns(Color::White));
+    if black.count() > 0 || white.count() > 0 {
+        println!("Overrun\tCount\tAvg(ms)\tMax(ms)");
+        for (name, overruns) in [("Black", black), ("White", white)] {
+            println!(
+                "{name}\t{}\t{}\t{}",
+                overruns.count(),
+                overruns.average().as_millis(),
+                overruns.max().as_millis()
+            );
+        }
+    }
+
+    let (black, white) = (stats.ponder(Color::Black), stats.ponder(Color::White));
+    if black.hit_rate().is_some() || white.hit_rate().is_some() {
+        println!("Ponder\tHit\tMiss\tRate");
+        for (name, ponder) in [("Black", black), ("White", white)] {
+            let rate = ponder
+                .hit_rate()
+                .map_or("-".to_string(), |r| format!("{:.1}%", r * 100.0));
+            println!("{name}\t{}\t{}\t{rate}", ponder.hits(), ponder.misses());
+        }
+    }
 }
 
 mod board;
 mod csa;
+mod jkf;
+mod jsonl;
+mod ki2;
+mod kif;
+mod multi;
+mod sfen;
 mod simple;
 mod usi;
+mod writer;
 
 pub use self::board::*;
 pub use self::csa::*;
+pub use self::jkf::*;
+pub use self::jsonl::*;
+pub use self::ki2::*;
+pub use self::kif::*;
+pub use self::multi::*;
+pub use self::sfen::*;
 pub use self::simple::*;
 pub use self::usi::*;
+pub use self::writer::*;
@@ -0,0 +1,132 @@
+use ::usi::{EngineOutput, GuiCommand};
+use shogi::Color;
+
+use crate::config::MatchConfig;
+use crate::environment::Event;
+use crate::stats::MatchStatistics;
+
+use super::Reporter;
+
+/// Passes every event to the display and the additional outputs.
+pub struct MultiReporter {
+    display: Box<dyn Reporter + Send>,
+    outputs: Vec<Box<dyn Reporter + Send>>,
+}
+
+impl MultiReporter {
+    pub fn new(
+        display: Box<dyn Reporter + Send>,
+        outputs: Vec<Box<dyn Reporter + Send>>,
+    ) -> MultiReporter {
+        MultiReporter { display, outputs }
+    }
+
+    fn reporters(&mut self) -> impl Iterator<Item = &mut Box<dyn Reporter + Send>> {
+        std::iter::once(&mut self.display).chain(self.outputs.iter_mut())
+    }
+}
+
+impl Reporter for MultiReporter {
+    fn on_match_started(&mut self, config: &MatchConfig) {
+        for reporter in self.reporters() {
+            reporter.on_match_started(config);
+        }
+    }
+
+    fn on_send_command(&mut self, stm: Color, command: &GuiCommand, arg: &str) {
+        for reporter in self.reporters() {
+            reporter.on_send_command(stm, command, arg);
+        }
+    }
+
+    fn on_receive_command(&mut self, stm: Color, output: &EngineOutput) {
+        for reporter in self.reporters() {
+            reporter.on_receive_command(stm, output);
+        }
+    }
+
+    fn on_engine_stderr(&mut self, stm: Color, line: &str) {
+        for reporter in self.reporters() {
+            reporter.on_engine_stderr(stm, line);
+        }
+    }
+
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        for reporter in self.reporters() {
+            reporter.on_game_event(event, stats);
+        }
+    }
+
+    fn on_match_finished(&mut self, stats: &MatchStatistics) {
+        for reporter in self.reporters() {
+            reporter.on_match_finished(stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the names of the reporter and the callbacks in the order they are called.
+    struct NamedReporter {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Reporter for NamedReporter {
+        fn on_engine_stderr(&mut self, _: Color, line: &str) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} {line}", self.name));
+        }
+
+        fn on_game_event(&mut self, _: &Event, _: &MatchStatistics) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} event", self.name));
+        }
+
+        fn on_match_finished(&mut self, _: &MatchStatistics) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} finished", self.name));
+        }
+    }
+
+    #[test]
+    fn forward_to_all() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let reporter = |name| {
+            Box::new(NamedReporter {
+                name,
+                calls: calls.clone(),
+            }) as Box<dyn Reporter + Send>
+        };
+        let mut multi = MultiReporter::new(reporter("display"), vec![reporter("a"), reporter("b")]);
+
+        let stats = MatchStatistics::new(1);
+        multi.on_engine_stderr(Color::Black, "x");
+        multi.on_game_event(&Event::IsReady, &stats);
+        multi.on_match_finished(&stats);
+
+        assert_eq!(
+            vec![
+                "display x",
+                "a x",
+                "b x",
+                "display event",
+                "a event",
+                "b event",
+                "display finished",
+                "a finished",
+                "b finished",
+            ],
+            *calls.lock().unwrap()
+        );
+    }
+}
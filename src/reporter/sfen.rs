@@ -0,0 +1,139 @@
+use indicatif::ProgressBar;
+use shogi::Color;
+
+use crate::environment::{Event, DEFAULT_SFEN};
+use crate::stats::MatchStatistics;
+
+use super::{RecordWriter, Reporter};
+
+/// Prints one line per game with the moves in the notation of USI `position` commands and the result.
+#[derive(Default)]
+pub struct SfenReporter {
+    current_bar: Option<ProgressBar>,
+    details: bool,
+    initial_pos: String,
+    moves: Vec<String>,
+    players: (String, String),
+    writer: RecordWriter,
+}
+
+impl SfenReporter {
+    /// Creates a reporter which also prints the termination reason and the engine names if `details` is true.
+    pub fn new(writer: RecordWriter, details: bool) -> SfenReporter {
+        SfenReporter {
+            details,
+            writer: writer.extension("sfen"),
+            ..Default::default()
+        }
+    }
+}
+
+impl Reporter for SfenReporter {
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        match *event {
+            Event::NewGame(ref game) => {
+                let current_game_num = stats.finished_games() + 1;
+                let num_games = stats.total_games();
+
+                self.current_bar = Some(self.writer.spinner(current_game_num, num_games));
+
+                self.writer.begin(current_game_num, game);
+                let sfen = game.pos.to_sfen();
+                self.initial_pos = if sfen == DEFAULT_SFEN {
+                    "startpos".to_string()
+                } else {
+                    format!("sfen {sfen}")
+                };
+                self.moves.clear();
+                self.players = (game.black_player.to_string(), game.white_player.to_string());
+            }
+            Event::NewTurn(ref game, _) => {
+                if let Some(last_move) = game.last_move() {
+                    self.moves.push(last_move.to_sfen());
+                }
+
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.set_message(format!("Move #{}", game.pos.ply()));
+                }
+            }
+            Event::GameOver(winner, reason) => {
+                if let Some(ref pbar) = self.current_bar {
+                    pbar.finish_and_clear();
+                }
+
+                let mut position = self.initial_pos.to_string();
+                if !self.moves.is_empty() {
+                    position = format!("{position} moves {}", self.moves.join(" "));
+                }
+                let result = match winner {
+                    Some(Color::Black) => "1-0",
+                    Some(Color::White) => "0-1",
+                    None => "1/2-1/2",
+                };
+
+                let mut fields = vec![position, result.to_string()];
+                if self.details {
+                    fields.push(reason.to_string());
+                    fields.push(self.players.0.to_string());
+                    fields.push(self.players.1.to_string());
+                }
+
+                self.writer.write(&format!("{}\n", fields.join("\t")));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{config, play_game, MockEngine, Reply, TempDir};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn startpos_with_details() {
+        let dir = TempDir::new("sfen_startpos");
+        let path = dir.join("games.sfen");
+        let reporter = Arc::new(Mutex::new(SfenReporter::new(
+            RecordWriter::file(path.clone()).hide_progress(),
+            true,
+        )));
+
+        let mut black = MockEngine::new("b");
+        let mut white = MockEngine::new("w");
+        for _ in 0..3 {
+            black = black.then(Reply::Move("5i4h")).then(Reply::Move("4h5i"));
+            white = white.then(Reply::Move("5a4b")).then(Reply::Move("4b5a"));
+        }
+        play_game(&config(), black, white, reporter).unwrap();
+
+        // The final move which repeats the position is also written.
+        let moves = ["5i4h 5a4b 4h5i 4b5a"; 3].join(" ");
+        assert_eq!(
+            format!("startpos moves {moves}\t1/2-1/2\trepetition\tb\tw\n"),
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn sfen_without_details() {
+        let dir = TempDir::new("sfen_sfen");
+        let path = dir.join("games.sfen");
+        let reporter = Arc::new(Mutex::new(SfenReporter::new(
+            RecordWriter::file(path.clone()).hide_progress(),
+            false,
+        )));
+
+        let mut config = config();
+        config.initial_pos = Some("8k/9/9/9/9/9/9/9/K8 b G 1".to_string());
+        let black = MockEngine::new("b").then(Reply::Move("G*5e"));
+        play_game(&config, black, MockEngine::new("w"), reporter).unwrap();
+
+        assert_eq!(
+            "sfen 8k/9/9/9/9/9/9/9/K8 b G 1 moves G*5e\t1-0\n",
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+}
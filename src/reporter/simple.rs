@@ -1,10 +1,10 @@
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget};
 
 use crate::environment::Event;
 use crate::stats::MatchStatistics;
 use shogi::Color;
 
-use super::Reporter;
+use super::{print_summary, spinner, Reporter};
 
 #[derive(Default)]
 pub struct SimpleReporter {
@@ -18,17 +18,11 @@ impl Reporter for SimpleReporter {
                 let current_game_num = stats.finished_games() + 1;
                 let num_games = stats.total_games();
 
-                let pbar = ProgressBar::new_spinner();
-                pbar.set_draw_target(ProgressDrawTarget::stderr());
-                pbar.set_style(
-                    ProgressStyle::default_spinner()
-                        .tick_chars("|/-\\ ")
-                        .template("{prefix:.bold.dim} {spinner} {msg}")
-                        .unwrap(),
-                );
-                pbar.set_prefix(format!("[{current_game_num}/{num_games}]"));
-                pbar.set_message("Starting...");
-                self.current_bar = Some(pbar);
+                self.current_bar = Some(spinner(
+                    current_game_num,
+                    num_games,
+                    ProgressDrawTarget::stderr(),
+                ));
             }
             Event::NewTurn(ref game, _) => {
                 if let Some(ref pbar) = self.current_bar {
@@ -50,4 +44,8 @@ impl Reporter for SimpleReporter {
             _ => {}
         }
     }
+
+    fn on_match_finished(&mut self, stats: &MatchStatistics) {
+        print_summary(stats);
+    }
 }
@@ -1,8 +1,13 @@
-use ::usi::{EngineOutput, GuiCommand};
+use ::usi::{EngineCommand, EngineOutput, GuiCommand};
 use shogi::Color;
 use std::io::Write;
+use std::mem;
 
-use super::Reporter;
+use crate::environment::Event;
+use crate::game::Game;
+use crate::stats::MatchStatistics;
+
+use super::{now_local, print_summary, RecordWriter, Reporter};
 
 #[derive(Default)]
 pub struct UsiReporter {}
@@ -17,4 +22,367 @@ impl Reporter for UsiReporter {
         let prefix = if color == Color::Black { "B" } else { "W" };
         write!(&mut std::io::stderr(), "{prefix}> {}", output.raw_str()).unwrap();
     }
+
+    fn on_engine_stderr(&mut self, color: Color, line: &str) {
+        let prefix = if color == Color::Black { "B" } else { "W" };
+        writeln!(&mut std::io::stderr(), "{prefix}! {line}").unwrap();
+    }
+
+    fn on_match_finished(&mut self, stats: &MatchStatistics) {
+        print_summary(stats);
+    }
+}
+
+/// Returns the current local time with milliseconds and the offset, e.g. "2024-05-01T21:34:56.789+09:00".
+fn timestamp() -> String {
+    let now = now_local();
+    let (hours, minutes, _) = now.offset().as_hms();
+    let sign = if hours < 0 || minutes < 0 { '-' } else { '+' };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}{sign}{:02}:{:02}",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.millisecond(),
+        hours.unsigned_abs(),
+        minutes.unsigned_abs()
+    )
+}
+
+/// Writes USI commands exchanged with each engine and lines written to its stderr
+/// to a log per game and engine, with the time of each line.
+///
+/// Lines are written as they arrive, so that the log is kept even if the engine or the match
+/// stops abruptly. Outputs arriving after the game is over, e.g. `bestmove` in response to `stop`,
+/// are written to the log of the game until the engine responds to the next `isready`.
+pub struct UsiLogReporter {
+    black: GameLog,
+    white: GameLog,
+}
+
+impl UsiLogReporter {
+    pub fn new(black_writer: RecordWriter, white_writer: RecordWriter) -> UsiLogReporter {
+        UsiLogReporter {
+            black: GameLog::new(black_writer.extension("black.log")),
+            white: GameLog::new(white_writer.extension("white.log")),
+        }
+    }
+
+    fn log(&mut self, color: Color) -> &mut GameLog {
+        if color == Color::Black {
+            &mut self.black
+        } else {
+            &mut self.white
+        }
+    }
+
+    fn push(&mut self, color: Color, marker: &str, line: &str) {
+        let prefix = if color == Color::Black { "B" } else { "W" };
+        self.log(color).push(&format_line(prefix, marker, line));
+    }
+}
+
+fn format_line(prefix: &str, marker: &str, line: &str) -> String {
+    format!(
+        "{} {prefix}{marker} {}\n",
+        timestamp(),
+        line.trim_end_matches(['\r', '\n'])
+    )
+}
+
+impl Reporter for UsiLogReporter {
+    fn on_send_command(&mut self, color: Color, command: &GuiCommand, raw_str: &str) {
+        let prefix = if color == Color::Black { "B" } else { "W" };
+        let log = self.log(color);
+        // "usi" is sent to an engine restarted after the previous one has been disconnected.
+        if *command == GuiCommand::Usi {
+            log.end();
+        }
+        log.push_next(&format_line(prefix, "<", raw_str));
+    }
+
+    fn on_receive_command(&mut self, color: Color, output: &EngineOutput) {
+        if matches!(output.response(), Some(EngineCommand::ReadyOk)) {
+            self.log(color).end_finished();
+        }
+        self.push(color, ">", output.raw_str());
+    }
+
+    fn on_engine_stderr(&mut self, color: Color, line: &str) {
+        self.push(color, "!", line);
+    }
+
+    fn on_game_event(&mut self, event: &Event, stats: &MatchStatistics) {
+        match *event {
+            Event::NewGame(ref game) => {
+                let game_num = stats.finished_games() + 1;
+                self.black.begin(game_num, game);
+                self.white.begin(game_num, game);
+            }
+            Event::GameOver(..) => {
+                self.black.finished = true;
+                self.white.finished = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn on_match_finished(&mut self, _: &MatchStatistics) {
+        self.black.end();
+        self.white.end();
+    }
+}
+
+/// The log of an engine, which holds lines sent between games until the next game starts.
+struct GameLog {
+    writer: RecordWriter,
+    out: Option<Box<dyn Write + Send>>,
+    pending: String,
+    /// Whether the game is over while the log is still open for the remaining outputs.
+    finished: bool,
+}
+
+impl GameLog {
+    fn new(writer: RecordWriter) -> GameLog {
+        GameLog {
+            writer,
+            out: None,
+            pending: String::new(),
+            finished: false,
+        }
+    }
+
+    fn begin(&mut self, game_num: u32, game: &Game) {
+        self.end();
+        self.writer.begin(game_num, game);
+        self.out = self.writer.open();
+
+        // Lines are discarded if the log can not be opened.
+        let pending = mem::take(&mut self.pending);
+        if self.out.is_some() {
+            self.push(&pending);
+        }
+    }
+
+    /// Pushes a line which belongs to the next game once the current game is over.
+    fn push_next(&mut self, line: &str) {
+        if self.finished {
+            self.pending.push_str(line);
+        } else {
+            self.push(line);
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        let out = match self.out {
+            Some(ref mut out) => out,
+            None => {
+                self.pending.push_str(line);
+                return;
+            }
+        };
+
+        if let Err(e) = out.write_all(line.as_bytes()).and_then(|_| out.flush()) {
+            eprintln!("failed to write the log: {e}");
+            self.out = None;
+        }
+    }
+
+    /// Closes the log if the game is over.
+    fn end_finished(&mut self) {
+        if self.finished {
+            self.end();
+        }
+    }
+
+    fn end(&mut self) {
+        self.finished = false;
+        if let Some(mut out) = self.out.take() {
+            let _ = out.flush();
+        }
+    }
+}
+
+// The log is also flushed if the match is aborted by an error.
+impl Drop for GameLog {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TimeControl;
+    use crate::game::GameOverReason;
+    use crate::mock::{config, play_game, MockEngine, Reply, TempDir};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use usi::EngineCommandReader;
+
+    fn reporter(dir: &Path) -> UsiLogReporter {
+        let writer = || RecordWriter::new(Some(dir.to_path_buf()), "{game}.{ext}".parse().unwrap());
+        UsiLogReporter::new(writer(), writer())
+    }
+
+    /// Returns lines of the handshake and `setoption` commands exchanged with the mock engine.
+    fn handshake(prefix: &str, name: &str) -> Vec<String> {
+        [
+            "< usi".to_string(),
+            format!("> id name {name}"),
+            "> id author mock".to_string(),
+            "> option name USI_Hash type spin default 16 min 1 max 1024".to_string(),
+            "> usiok".to_string(),
+            "< isready".to_string(),
+            "> readyok".to_string(),
+            "< setoption name USI_Hash value 16".to_string(),
+            "< setoption name USI_Ponder value false".to_string(),
+        ]
+        .iter()
+        .map(|line| format!("{prefix}{line}"))
+        .collect()
+    }
+
+    /// Returns lines of the log without timestamps.
+    fn read_log(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn write_as_lines_arrive() {
+        let dir = TempDir::new("usi_lines");
+        let mut reporter = reporter(&dir);
+        let stats = MatchStatistics::new(1);
+        let mut game = Game::new(TimeControl::Byoyomi {
+            black_time: Duration::from_secs(0),
+            white_time: Duration::from_secs(0),
+            black_byoyomi: Duration::from_secs(0),
+            white_byoyomi: Duration::from_secs(0),
+        });
+
+        reporter.on_send_command(Color::Black, &GuiCommand::Usi, "usi");
+        reporter.on_game_event(&Event::NewGame(&mut game), &stats);
+        // Lines sent before the game are written when the game starts.
+        assert_eq!(vec!["B< usi"], read_log(&dir.join("1.black.log")));
+
+        reporter.on_engine_stderr(Color::Black, "warning\n");
+        assert_eq!(
+            vec!["B< usi", "B! warning"],
+            read_log(&dir.join("1.black.log"))
+        );
+        assert!(read_log(&dir.join("1.white.log")).is_empty());
+
+        drop(reporter);
+    }
+
+    #[test]
+    fn disconnected_engine() {
+        let dir = TempDir::new("usi_disconnected");
+        let reporter = Arc::new(Mutex::new(reporter(&dir)));
+
+        let black = MockEngine::new("b").then(Reply::Move("7g7f"));
+        let white = MockEngine::new("w").then(Reply::Crash);
+        let result = play_game(&config(), black, white, reporter.clone()).unwrap();
+        assert_eq!(GameOverReason::Disconnected, result.reason);
+
+        // The log of the disconnected engine ends with the last command it received.
+        let position =
+            "position sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+        let go = "go btime 0 wtime 0 byoyomi 5000";
+        assert_eq!(
+            [
+                handshake("W", "w"),
+                vec![
+                    "W< isready".to_string(),
+                    "W> readyok".to_string(),
+                    "W< usinewgame".to_string(),
+                    format!("W< {position} moves 7g7f"),
+                    format!("W< {go}"),
+                ]
+            ]
+            .concat(),
+            read_log(&dir.join("1.white.log"))
+        );
+        assert_eq!(
+            [
+                handshake("B", "b"),
+                vec![
+                    "B< isready".to_string(),
+                    "B> readyok".to_string(),
+                    "B< usinewgame".to_string(),
+                    format!("B< {position}"),
+                    format!("B< {go}"),
+                    "B> bestmove 7g7f".to_string(),
+                    "B< stop".to_string(),
+                    "B< gameover win".to_string(),
+                ]
+            ]
+            .concat(),
+            read_log(&dir.join("1.black.log"))
+        );
+
+        reporter
+            .lock()
+            .unwrap()
+            .on_match_finished(&MatchStatistics::new(1));
+    }
+
+    #[test]
+    fn output_after_game_over() {
+        let dir = TempDir::new("usi_after_game_over");
+        let reporter = Arc::new(Mutex::new(reporter(&dir)));
+
+        let mut config = config();
+        config.black_engine.ponder = true;
+        let black = MockEngine::new("b")
+            .then(Reply::MoveWithPonder("7g7f", "3c3d"))
+            .stop_after(Duration::from_millis(100));
+        let white = MockEngine::new("w").then(Reply::Crash);
+        let result = play_game(&config, black, white, reporter.clone()).unwrap();
+
+        // "bestmove" in response to "stop" after the game is written to the log of the game.
+        let log = dir.join("1.black.log");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while read_log(&log).last().map(String::as_str) != Some("B> bestmove 1g1f") {
+            assert!(Instant::now() < deadline, "{:?}", read_log(&log));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut reporter = reporter.lock().unwrap();
+        let mut stats = MatchStatistics::new(2);
+        stats.record_game(&result);
+        let mut game = Game::new(TimeControl::Byoyomi {
+            black_time: Duration::from_secs(0),
+            white_time: Duration::from_secs(0),
+            black_byoyomi: Duration::from_secs(0),
+            white_byoyomi: Duration::from_secs(0),
+        });
+        let readyok = EngineCommandReader::new(&b"readyok\n"[..])
+            .next_command()
+            .unwrap();
+        reporter.on_send_command(Color::Black, &GuiCommand::IsReady, "isready");
+        reporter.on_receive_command(Color::Black, &readyok);
+        reporter.on_game_event(&Event::NewGame(&mut game), &stats);
+
+        let lines = read_log(&log);
+        assert_eq!(
+            vec!["B< stop", "B< gameover win", "B> bestmove 1g1f"],
+            lines[lines.len() - 3..]
+        );
+        assert_eq!(
+            vec!["B< isready", "B> readyok"],
+            read_log(&dir.join("2.black.log"))
+        );
+        reporter.on_match_finished(&stats);
+    }
 }
@@ -0,0 +1,252 @@
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::config::{FilenameFields, FilenameTemplate};
+use crate::game::Game;
+
+use super::spinner;
+
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// Determines the offset of the local time, which must be called before any thread is started
+/// as the offset can not be determined safely in multi-threaded processes.
+pub fn init_local_offset() {
+    LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC));
+}
+
+/// Returns the current local time, falling back to UTC if the offset is unknown.
+pub fn now_local() -> OffsetDateTime {
+    let offset = LOCAL_OFFSET.get().copied().unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::now_utc().to_offset(offset)
+}
+
+/// Writes game records to stdout, to a file per game if the output directory is set,
+/// or to a single file if the file is set.
+pub struct RecordWriter {
+    output_dir: Option<PathBuf>,
+    filename: FilenameTemplate,
+    file: Option<PathBuf>,
+    extension: &'static str,
+    separator: &'static str,
+    path: Option<PathBuf>,
+    written: u32,
+    progress: bool,
+}
+
+impl RecordWriter {
+    pub fn new(output_dir: Option<PathBuf>, filename: FilenameTemplate) -> RecordWriter {
+        RecordWriter {
+            output_dir,
+            filename,
+            file: None,
+            extension: "txt",
+            separator: "",
+            path: None,
+            written: 0,
+            progress: true,
+        }
+    }
+
+    /// Creates a writer which writes all games to the given file.
+    pub fn file(path: PathBuf) -> RecordWriter {
+        RecordWriter {
+            file: Some(path),
+            ..RecordWriter::default()
+        }
+    }
+
+    /// Sets the extension substituted for "{ext}" in the file name template.
+    pub fn extension(mut self, extension: &'static str) -> RecordWriter {
+        self.extension = extension;
+        self
+    }
+
+    /// Sets the text written between records in stdout or a single file.
+    pub fn separator(mut self, separator: &'static str) -> RecordWriter {
+        self.separator = separator;
+        self
+    }
+
+    /// Hides the progress of games, e.g. when the records are written along with another display.
+    pub fn hide_progress(mut self) -> RecordWriter {
+        self.progress = false;
+        self
+    }
+
+    /// Returns where the progress of games is drawn.
+    pub fn progress_target(&self) -> ProgressDrawTarget {
+        if self.progress {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        }
+    }
+
+    /// Returns the spinner showing the progress of the given game where the progress is drawn.
+    pub fn spinner(&self, game_num: u32, num_games: u32) -> ProgressBar {
+        spinner(game_num, num_games, self.progress_target())
+    }
+
+    /// Decides the file name of the record of a new game.
+    pub fn begin(&mut self, game_num: u32, game: &Game) {
+        let dir = match self.output_dir {
+            Some(ref dir) => dir,
+            None => return,
+        };
+
+        let now = now_local();
+        let date = format!("{:04}{:02}{:02}", now.year(), now.month() as u8, now.day());
+        let time = format!("{:02}{:02}{:02}", now.hour(), now.minute(), now.second());
+        let black = sanitize(&game.black_player);
+        let white = sanitize(&game.white_player);
+        let fields = FilenameFields {
+            date: &date,
+            time: &time,
+            game: game_num,
+            black: &black,
+            white: &white,
+            ext: self.extension,
+        };
+
+        self.path = match self.filename.render(&fields) {
+            Ok(name) => Some(dir.join(name)),
+            Err(e) => {
+                eprintln!("{e}");
+                None
+            }
+        };
+    }
+
+    /// Writes the record of the current game.
+    pub fn write(&mut self, record: &str) {
+        let separator = if self.written > 0 { self.separator } else { "" };
+
+        if let Some(ref path) = self.file {
+            if let Err(e) = append(path, &format!("{separator}{record}"), self.written == 0) {
+                eprintln!("failed to write the record to {}: {e}", path.display());
+            }
+        } else if self.output_dir.is_none() {
+            print!("{separator}{record}");
+        } else if let Some(path) = self.path.take() {
+            if let Err(e) = write_atomically(&path, record) {
+                eprintln!("failed to write the record to {}: {e}", path.display());
+            }
+        }
+        self.written += 1;
+    }
+
+    /// Opens the record of the current game to write it incrementally, unlike `write` which writes
+    /// the whole record at once. Returns `None` if the file can not be opened.
+    pub fn open(&mut self) -> Option<Box<dyn Write + Send>> {
+        let separator = if self.written > 0 { self.separator } else { "" };
+        let (path, truncate) = match (&self.file, &self.output_dir) {
+            (Some(path), _) => (path.clone(), self.written == 0),
+            (None, Some(_)) => (self.path.take()?, true),
+            (None, None) => {
+                self.written += 1;
+                print!("{separator}");
+                return Some(Box::new(io::stdout()));
+            }
+        };
+        self.written += 1;
+
+        let result = open_file(&path, truncate).and_then(|mut f| {
+            f.write_all(separator.as_bytes())?;
+            Ok(f)
+        });
+        match result {
+            Ok(f) => Some(Box::new(f)),
+            Err(e) => {
+                eprintln!("failed to open {}: {e}", path.display());
+                None
+            }
+        }
+    }
+}
+
+impl Default for RecordWriter {
+    fn default() -> RecordWriter {
+        RecordWriter::new(None, FilenameTemplate::default())
+    }
+}
+
+/// Replaces characters which are not safe in file names, e.g. path separators in engine names.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Opens the file to append to, which is truncated first if `truncate` is true.
+fn open_file(path: &Path, truncate: bool) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(truncate)
+        .append(!truncate)
+        .open(path)
+}
+
+/// Appends the contents to the file, which is truncated first if `truncate` is true.
+fn append(path: &Path, contents: &str, truncate: bool) -> io::Result<()> {
+    open_file(path, truncate)?.write_all(contents.as_bytes())
+}
+
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // The record is renamed after being written completely, so that no partial record is left
+    // if the match is interrupted.
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{file_name}.tmp"));
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(contents.as_bytes())?;
+    f.sync_all()?;
+    drop(f);
+
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::TempDir;
+
+    #[test]
+    fn sanitize_names() {
+        assert_eq!("Engine-1.0", sanitize("Engine-1.0"));
+        assert_eq!("a_b_c_d", sanitize("a/b\\c d"));
+        assert_eq!("水匠5", sanitize("水匠5"));
+        assert_eq!("__.._x", sanitize("<:../x"));
+    }
+
+    #[test]
+    fn write_record_atomically() {
+        let dir = TempDir::new("writer");
+        let path = dir.join("sub").join("game.csa");
+
+        write_atomically(&path, "first").unwrap();
+        assert_eq!("first", fs::read_to_string(&path).unwrap());
+
+        write_atomically(&path, "second").unwrap();
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        // The temporary file is renamed to the record.
+        assert!(!dir.join("sub").join(".game.csa.tmp").exists());
+    }
+}
@@ -0,0 +1,158 @@
+use shogi::Color;
+use std::time::Duration;
+
+use crate::config::{EngineConfig, MatchConfig};
+use crate::error::Error;
+use crate::json::JsonValue;
+use crate::stats::MatchStatistics;
+
+fn millis(d: Duration) -> i64 {
+    d.as_millis() as i64
+}
+
+fn record(wins: u32, draws: u32, losses: u32) -> JsonValue {
+    JsonValue::object()
+        .field("wins", i64::from(wins))
+        .field("draws", i64::from(draws))
+        .field("losses", i64::from(losses))
+}
+
+/// Returns the time, search and ponder statistics of the given player.
+pub fn player_stats(stats: &MatchStatistics, c: Color) -> JsonValue {
+    let time = stats.time(c);
+    let search = stats.search(c);
+    let ponder = stats.ponder(c);
+
+    JsonValue::object()
+        .field("moves", time.moves() as i64)
+        .field("average_time", millis(time.average()))
+        .field("median_time", millis(time.median()))
+        .field("time_losses", i64::from(time.time_losses()))
+        .field("overruns", i64::from(stats.overruns(c).count()))
+        .field("average_depth", search.average_depth())
+        .field("average_nps", search.average_nps().map(|n| n as i64))
+        .field("ponder_hits", i64::from(ponder.hits()))
+        .field("ponder_misses", i64::from(ponder.misses()))
+}
+
+/// Returns the final results of the match, which are written by `--results-json`.
+///
+/// Engines keep their colors throughout the match, so results of each engine are the same as
+/// those of its color.
+pub fn match_results(
+    config: &MatchConfig,
+    stats: &MatchStatistics,
+    names: (&str, &str),
+    duration: Duration,
+) -> Result<JsonValue, Error> {
+    let (black_wins, white_wins, draws) =
+        (stats.black_wins(), stats.white_wins(), stats.draw_games());
+    let engine = |c: Color, name: &str, config: &EngineConfig| {
+        let (wins, losses) = if c == Color::Black {
+            (black_wins, white_wins)
+        } else {
+            (white_wins, black_wins)
+        };
+        let options = config
+            .options
+            .iter()
+            .fold(JsonValue::object(), |options, (key, value)| {
+                options.field(key, value.as_str())
+            });
+
+        JsonValue::object()
+            .field("name", name)
+            .field("path", config.engine_path.as_str())
+            .field("color", if c == Color::Black { "black" } else { "white" })
+            .field("options", options)
+            .field("results", record(wins, draws, losses))
+    };
+
+    let terminations = stats
+        .terminations()
+        .iter()
+        .fold(JsonValue::object(), |terminations, (reason, count)| {
+            terminations.field(&reason.to_string(), i64::from(*count))
+        });
+    let elo = stats.elo().map(|(diff, error)| {
+        JsonValue::object()
+            .field("difference", diff)
+            .field("error", error)
+    });
+
+    Ok(JsonValue::object()
+        .field("usi_run", env!("CARGO_PKG_VERSION"))
+        .field("duration", millis(duration))
+        .field("time_control", config.time_control()?.to_string())
+        .field("max_ply", config.max_ply.map(i32::from))
+        .field(
+            "openings",
+            vec![config.initial_pos.as_deref().unwrap_or("startpos")],
+        )
+        .field("games", i64::from(stats.finished_games()))
+        .field(
+            "engines",
+            vec![
+                engine(Color::Black, names.0, &config.black_engine),
+                engine(Color::White, names.1, &config.white_engine),
+            ],
+        )
+        .field(
+            "colors",
+            JsonValue::object()
+                .field("black", record(black_wins, draws, white_wins))
+                .field("white", record(white_wins, draws, black_wins)),
+        )
+        .field("terminations", terminations)
+        .field("elo", elo)
+        .field(
+            "players",
+            JsonValue::object()
+                .field("black", player_stats(stats, Color::Black))
+                .field("white", player_stats(stats, Color::White)),
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameOverReason, GameResult};
+    use crate::mock::config;
+
+    #[test]
+    fn results() {
+        let mut config = config();
+        config.max_ply = Some(256);
+        config.black_engine.engine_path = "./b".to_string();
+        config.black_engine.options = vec![("USI_Hash".to_string(), "256".to_string())];
+        config.white_engine.engine_path = "./w".to_string();
+
+        let mut stats = MatchStatistics::new(4);
+        for (winner, reason) in [
+            (Some(Color::Black), GameOverReason::Resign),
+            (Some(Color::Black), GameOverReason::OutOfTime),
+            (None, GameOverReason::MaxPly),
+            (Some(Color::White), GameOverReason::Resign),
+        ] {
+            stats.record_game(&GameResult::new(winner, reason));
+        }
+
+        let results =
+            match_results(&config, &stats, ("B", "W"), Duration::from_millis(1234)).unwrap();
+        let expected = [
+            r#"{"usi_run":"VERSION","duration":1234,"time_control":"0s + 5s byoyomi","max_ply":256,"openings":["startpos"],"games":4,"#,
+            r#""engines":[{"name":"B","path":"./b","color":"black","options":{"USI_Hash":"256"},"results":{"wins":2,"draws":1,"losses":1}},"#,
+            r#"{"name":"W","path":"./w","color":"white","options":{},"results":{"wins":1,"draws":1,"losses":2}}],"#,
+            r#""colors":{"black":{"wins":2,"draws":1,"losses":1},"white":{"wins":1,"draws":1,"losses":2}},"#,
+            r#""terminations":{"resign":2,"out_of_time":1,"max_ply":1},"elo":{"difference":88.73949984654251,"error":null},"#,
+            r#""players":{"black":{"moves":0,"average_time":0,"median_time":0,"time_losses":0,"overruns":0,"average_depth":null,"average_nps":null,"ponder_hits":0,"ponder_misses":0},"#,
+            r#""white":{"moves":0,"average_time":0,"median_time":0,"time_losses":1,"overruns":0,"average_depth":null,"average_nps":null,"ponder_hits":0,"ponder_misses":0}}}"#,
+        ];
+        assert_eq!(
+            expected
+                .concat()
+                .replace("VERSION", env!("CARGO_PKG_VERSION")),
+            results.to_string()
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use toml::Value;
+use usi::OptionKind;
+
+use crate::profile::{combo_vars, option_type, EngineProfile};
+
+/// The example configuration, whose settings and comments are shared with generated configurations.
+const EXAMPLE: &str = include_str!("../example.toml");
+
+/// Generates a match configuration in TOML format with the options reported by each engine.
+///
+/// Settings of the match and the engines are the same as example.toml, except the paths and
+/// the options of the engines.
+pub fn generate_config(
+    black_path: &str,
+    black: &EngineProfile,
+    white_path: &str,
+    white: &EngineProfile,
+) -> String {
+    let (header, rest) = EXAMPLE.split_once("\n[black]\n").unwrap_or((EXAMPLE, ""));
+    let engine = rest
+        .split_once("\n[white]\n")
+        .map_or(rest, |(engine, _)| engine);
+
+    let mut s = header.to_string();
+    write_engine(&mut s, "black", engine, black_path, black);
+    write_engine(&mut s, "white", engine, white_path, white);
+
+    s
+}
+
+/// Returns the absolute path of the engine, so that the engine can be launched in its directory.
+///
+/// Engines without any directory, which are found in PATH, are returned as is.
+pub fn absolute_engine_path(engine_path: &str) -> String {
+    let path = Path::new(engine_path);
+    if path.parent().is_none_or(|p| p.as_os_str().is_empty()) {
+        return engine_path.to_string();
+    }
+
+    fs::canonicalize(path).map_or(engine_path.to_string(), |p| p.to_string_lossy().to_string())
+}
+
+/// Returns the directory containing the engine, where engines usually look for their data files.
+pub fn default_working_dir(engine_path: &str) -> String {
+    Path::new(engine_path)
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Writes the settings of the engine based on the template of the black engine in example.toml.
+fn write_engine(
+    s: &mut String,
+    color: &str,
+    template: &str,
+    engine_path: &str,
+    profile: &EngineProfile,
+) {
+    let _ = writeln!(s, "\n[{color}]\n# {} by {}", profile.name, profile.author);
+
+    // Engines found in PATH are launched in the current directory.
+    let working_dir = default_working_dir(engine_path);
+    let template = template.replace("black", color);
+    let mut lines = template.lines().peekable();
+    while let Some(line) = lines.next() {
+        let next = lines.peek().copied().unwrap_or_default();
+        if line.starts_with("engine_path =") {
+            let _ = writeln!(
+                s,
+                "engine_path = {}",
+                Value::String(engine_path.to_string())
+            );
+        } else if line.starts_with("working_dir =") {
+            if !working_dir.is_empty() {
+                let _ = writeln!(
+                    s,
+                    "working_dir = {}",
+                    Value::String(working_dir.to_string())
+                );
+            }
+        } else if next.starts_with("working_dir =") && working_dir.is_empty() {
+            // The comment of "working_dir" is omitted along with the key.
+        } else if line.trim_start().starts_with(&format!("[{color}.options]")) {
+            let _ = writeln!(
+                s,
+                "    # Values below are the defaults reported by the engine."
+            );
+            let _ = writeln!(s, "{line}");
+            break;
+        } else {
+            let _ = writeln!(s, "{line}");
+        }
+    }
+
+    for opt in &profile.options {
+        // "USI_Ponder" is controlled by "ponder" setting above.
+        if opt.name == "USI_Ponder" {
+            continue;
+        }
+
+        let key = toml_key(&opt.name);
+        let value = match opt.value {
+            OptionKind::Check { default } => default.map(Value::Boolean),
+            OptionKind::Spin { default, .. } => default.map(|v| Value::Integer(v.into())),
+            OptionKind::Combo { ref default, .. }
+            | OptionKind::String { ref default }
+            | OptionKind::Filename { ref default } => default
+                .as_ref()
+                .map(|v| Value::String(if v == "<empty>" { "" } else { v }.to_string())),
+            // Buttons do not have any value to be set in advance.
+            OptionKind::Button { .. } => None,
+        };
+
+        let mut comment = format!("({})", option_type(&opt.value));
+        match opt.value {
+            OptionKind::Spin {
+                min: Some(min),
+                max: Some(max),
+                ..
+            } => {
+                let _ = write!(comment, " {min} - {max}");
+            }
+            OptionKind::Combo { ref vars, .. } => {
+                let _ = write!(comment, " {}", combo_vars(vars).join(" / "));
+            }
+            _ => {}
+        }
+
+        match value {
+            Some(v) => {
+                let _ = writeln!(s, "    # {comment}\n    {key} = {v}");
+            }
+            None => {
+                let _ = writeln!(s, "    # {comment}\n    # {key} =");
+            }
+        }
+    }
+}
+
+fn toml_key(name: &str) -> String {
+    let is_bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if is_bare {
+        name.to_string()
+    } else {
+        Value::String(name.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MatchConfig;
+    use usi::OptionParams;
+
+    fn profile() -> EngineProfile {
+        let option = |name: &str, value: OptionKind| OptionParams {
+            name: name.to_string(),
+            value,
+        };
+
+        EngineProfile {
+            name: "Engine".to_string(),
+            author: "author".to_string(),
+            options: vec![
+                option(
+                    "USI_Hash",
+                    OptionKind::Spin {
+                        default: Some(256),
+                        min: Some(1),
+                        max: Some(1024),
+                    },
+                ),
+                option(
+                    "USI_Ponder",
+                    OptionKind::Check {
+                        default: Some(true),
+                    },
+                ),
+                option(
+                    "Book File",
+                    OptionKind::Filename {
+                        default: Some("<empty>".to_string()),
+                    },
+                ),
+                option(
+                    "Style",
+                    OptionKind::Combo {
+                        default: Some("Normal".to_string()),
+                        vars: vec!["Solid".to_string(), "Normal".to_string()],
+                    },
+                ),
+                option("Clear Hash", OptionKind::Button { default: None }),
+            ],
+        }
+    }
+
+    #[test]
+    fn load_generated_config() {
+        let s = generate_config("engine", &profile(), "/path/to/engine", &profile());
+        assert!(!s.contains("working_dir = \"\""), "{s}");
+
+        let mut config = MatchConfig::default();
+        config.load_str(&s).unwrap();
+
+        assert_eq!(10, config.num_games);
+        assert_eq!("engine", config.black_engine.engine_path);
+        assert_eq!("", config.black_engine.working_dir);
+        assert_eq!("/path/to/engine", config.white_engine.engine_path);
+        assert_eq!("/path/to", config.white_engine.working_dir);
+
+        let option = |name: &str| {
+            config
+                .black_engine
+                .options
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(3, config.black_engine.options.len());
+        assert_eq!(Some("256"), option("USI_Hash"));
+        assert_eq!(Some("<empty>"), option("Book File"));
+        assert_eq!(Some("Normal"), option("Style"));
+    }
+
+    #[test]
+    fn same_settings_as_example() {
+        let s = generate_config("/path/to/b", &profile(), "/path/to/w", &profile());
+
+        // Lines of example.toml before the engines are kept, as well as the comments of the engines.
+        let (header, rest) = EXAMPLE.split_once("\n[black]\n").unwrap();
+        assert!(s.starts_with(header), "{s}");
+        let black = rest.split_once("\n[white]\n").unwrap().0;
+        for line in black
+            .lines()
+            .filter(|line| line.trim_start().starts_with('#'))
+        {
+            assert!(s.contains(line), "{line}");
+        }
+
+        let mut generated = MatchConfig::default();
+        let mut example = MatchConfig::default();
+        generated.load_str(&s).unwrap();
+        example.load_str(EXAMPLE).unwrap();
+        assert_eq!(example.num_games, generated.num_games);
+        assert_eq!(example.max_ply, generated.max_ply);
+        assert_eq!(
+            example.time_control().unwrap().to_string(),
+            generated.time_control().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn relative_engine_path() {
+        // Paths with directories are resolved, as the engine is launched in its directory.
+        let path = absolute_engine_path("src/main.rs");
+        assert!(Path::new(&path).is_absolute(), "{path}");
+        assert!(path.ends_with("src/main.rs"), "{path}");
+        assert!(Path::new(&default_working_dir(&path)).is_absolute());
+
+        assert_eq!("engine", absolute_engine_path("engine"));
+        assert_eq!("/no/such/engine", absolute_engine_path("/no/such/engine"));
+    }
+}
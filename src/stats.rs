@@ -1,4 +1,204 @@
 use shogi::Color;
+use std::time::Duration;
+
+use crate::engine::SearchInfo;
+use crate::game::{GameOverReason, GameResult};
+
+/// Aggregates moves which exceeded the allotted time.
+#[derive(Debug, Default)]
+pub struct OverrunStatistics {
+    count: u32,
+    total: Duration,
+    max: Duration,
+}
+
+impl OverrunStatistics {
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn average(&self) -> Duration {
+        average(self.count, self.total)
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    fn record(&mut self, overrun: Duration) {
+        self.count += 1;
+        self.total += overrun;
+        self.max = self.max.max(overrun);
+    }
+}
+
+/// Counts how often ponder moves were hit.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PonderStatistics {
+    hits: u32,
+    misses: u32,
+}
+
+impl PonderStatistics {
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u32 {
+        self.misses
+    }
+
+    /// Returns the ratio of hits to all ponder searches, or `None` if the engine never pondered.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            None
+        } else {
+            Some(f64::from(self.hits) / f64::from(total))
+        }
+    }
+
+    pub fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &PonderStatistics) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+    }
+}
+
+/// Represents a phase of a game, divided by ply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl GamePhase {
+    pub const ALL: [GamePhase; 3] = [
+        GamePhase::Opening,
+        GamePhase::Middlegame,
+        GamePhase::Endgame,
+    ];
+
+    /// Returns the phase of the given ply, where the opening lasts 40 plies
+    /// and the endgame starts after 100 plies.
+    pub fn of(ply: u16) -> GamePhase {
+        match ply {
+            0..=40 => GamePhase::Opening,
+            41..=100 => GamePhase::Middlegame,
+            _ => GamePhase::Endgame,
+        }
+    }
+}
+
+/// Aggregates the time spent by a player.
+#[derive(Debug, Default)]
+pub struct TimeStatistics {
+    move_times: Vec<Duration>,
+    phase_times: [(u32, Duration); 3],
+    remaining: Duration,
+    games: u32,
+    time_losses: u32,
+}
+
+impl TimeStatistics {
+    pub fn moves(&self) -> usize {
+        self.move_times.len()
+    }
+
+    pub fn average(&self) -> Duration {
+        average(self.move_times.len() as u32, self.move_times.iter().sum())
+    }
+
+    pub fn median(&self) -> Duration {
+        let mut times = self.move_times.clone();
+        times.sort();
+
+        match times.len() {
+            0 => Duration::from_secs(0),
+            n if n % 2 == 0 => (times[n / 2 - 1] + times[n / 2]) / 2,
+            n => times[n / 2],
+        }
+    }
+
+    /// Returns the average time per move in the given phase.
+    pub fn phase_average(&self, phase: GamePhase) -> Duration {
+        let (count, total) = self.phase_times[phase as usize];
+        average(count, total)
+    }
+
+    /// Returns the average remaining time on the clock at the end of games.
+    pub fn average_remaining(&self) -> Duration {
+        average(self.games, self.remaining)
+    }
+
+    pub fn time_losses(&self) -> u32 {
+        self.time_losses
+    }
+
+    fn record_move(&mut self, ply: u16, elapsed: Duration) {
+        self.move_times.push(elapsed);
+
+        let phase = &mut self.phase_times[GamePhase::of(ply) as usize];
+        phase.0 += 1;
+        phase.1 += elapsed;
+    }
+
+    fn record_game(&mut self, remaining: Duration, lost_on_time: bool) {
+        self.games += 1;
+        self.remaining += remaining;
+        if lost_on_time {
+            self.time_losses += 1;
+        }
+    }
+}
+
+/// Aggregates search statistics reported by a player.
+#[derive(Debug, Default)]
+pub struct SearchStatistics {
+    depths: (u32, u64),
+    nps: (u32, u64),
+}
+
+impl SearchStatistics {
+    /// Returns the average depth of searches, or `None` if no depth has been reported.
+    pub fn average_depth(&self) -> Option<f64> {
+        let (count, total) = self.depths;
+        (count > 0).then(|| total as f64 / f64::from(count))
+    }
+
+    /// Returns the average NPS of searches, or `None` if no NPS has been reported.
+    pub fn average_nps(&self) -> Option<u64> {
+        let (count, total) = self.nps;
+        (count > 0).then(|| total / u64::from(count))
+    }
+
+    fn record_move(&mut self, info: &SearchInfo) {
+        if let Some(depth) = info.depth {
+            self.depths.0 += 1;
+            self.depths.1 += depth.max(0) as u64;
+        }
+        if let Some(nps) = info.nps {
+            self.nps.0 += 1;
+            self.nps.1 += nps;
+        }
+    }
+}
+
+fn average(count: u32, total: Duration) -> Duration {
+    if count == 0 {
+        Duration::from_secs(0)
+    } else {
+        total / count
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct MatchStatistics {
@@ -7,6 +207,15 @@ pub struct MatchStatistics {
     draw_games: u32,
     finished_games: u32,
     total_games: u32,
+    black_overruns: OverrunStatistics,
+    white_overruns: OverrunStatistics,
+    black_ponder: PonderStatistics,
+    white_ponder: PonderStatistics,
+    black_time: TimeStatistics,
+    white_time: TimeStatistics,
+    black_search: SearchStatistics,
+    white_search: SearchStatistics,
+    terminations: Vec<(GameOverReason, u32)>,
 }
 
 impl MatchStatistics {
@@ -37,8 +246,105 @@ impl MatchStatistics {
         self.total_games
     }
 
-    pub fn record_game(&mut self, winner: Option<Color>) {
-        if let Some(winner) = winner {
+    /// Returns overruns of the given player, including those tolerated by the time margin.
+    pub fn overruns(&self, c: Color) -> &OverrunStatistics {
+        if c == Color::Black {
+            &self.black_overruns
+        } else {
+            &self.white_overruns
+        }
+    }
+
+    pub fn ponder(&self, c: Color) -> &PonderStatistics {
+        if c == Color::Black {
+            &self.black_ponder
+        } else {
+            &self.white_ponder
+        }
+    }
+
+    pub fn time(&self, c: Color) -> &TimeStatistics {
+        if c == Color::Black {
+            &self.black_time
+        } else {
+            &self.white_time
+        }
+    }
+
+    pub fn search(&self, c: Color) -> &SearchStatistics {
+        if c == Color::Black {
+            &self.black_search
+        } else {
+            &self.white_search
+        }
+    }
+
+    /// Returns the number of games ended by each reason, in the order of their first occurrence.
+    pub fn terminations(&self) -> &[(GameOverReason, u32)] {
+        &self.terminations
+    }
+
+    /// Returns the Elo difference of the black engine over the white engine and its 95% error margin,
+    /// or `None` if no game has finished or one engine scored every point.
+    ///
+    /// The error margin is `None` if the 95% interval of the score reaches 0% or 100%, where the
+    /// difference is unbounded.
+    pub fn elo(&self) -> Option<(f64, Option<f64>)> {
+        let n = f64::from(self.finished_games);
+        let (wins, draws, losses) = (
+            f64::from(self.black_wins),
+            f64::from(self.draw_games),
+            f64::from(self.white_wins),
+        );
+        let score = (wins + draws / 2.0) / n;
+        if !(score > 0.0 && score < 1.0) {
+            return None;
+        }
+
+        let variance =
+            (wins * (1.0 - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2))
+                / n;
+        let margin = 1.96 * (variance / n).sqrt();
+        let elo = |s: f64| -400.0 * (1.0 / s - 1.0).log10();
+        let error = if score - margin > 0.0 && score + margin < 1.0 {
+            Some((elo(score + margin) - elo(score - margin)) / 2.0)
+        } else {
+            None
+        };
+
+        Some((elo(score), error))
+    }
+
+    pub fn record_game(&mut self, result: &GameResult) {
+        self.black_ponder.merge(&result.black_ponder);
+        self.white_ponder.merge(&result.white_ponder);
+
+        for m in &result.moves {
+            if m.color == Color::Black {
+                self.black_time.record_move(m.ply, m.elapsed);
+                self.black_search.record_move(&m.info);
+            } else {
+                self.white_time.record_move(m.ply, m.elapsed);
+                self.white_search.record_move(&m.info);
+            }
+        }
+        let lost_on_time = |c: Color| {
+            matches!(result.reason, GameOverReason::OutOfTime) && result.winner == Some(c.flip())
+        };
+        self.black_time
+            .record_game(result.black_time, lost_on_time(Color::Black));
+        self.white_time
+            .record_game(result.white_time, lost_on_time(Color::White));
+
+        for &(c, overrun) in &result.overruns {
+            if c == Color::Black {
+                self.black_overruns.record(overrun);
+            } else {
+                self.white_overruns.record(overrun);
+            }
+        }
+
+        if let Some(winner) = result.winner {
             if winner == Color::Black {
                 self.black_wins += 1;
             } else {
@@ -47,6 +353,62 @@ impl MatchStatistics {
         } else {
             self.draw_games += 1;
         }
+        match self
+            .terminations
+            .iter_mut()
+            .find(|(r, _)| *r == result.reason)
+        {
+            Some((_, count)) => *count += 1,
+            None => self.terminations.push((result.reason, 1)),
+        }
         self.finished_games += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(wins: u32, draws: u32, losses: u32) -> MatchStatistics {
+        let mut stats = MatchStatistics::new(wins + draws + losses);
+        let results = [
+            (Some(Color::Black), GameOverReason::Resign, wins),
+            (None, GameOverReason::Repetition, draws),
+            (Some(Color::White), GameOverReason::Resign, losses),
+        ];
+        for (winner, reason, n) in results {
+            for _ in 0..n {
+                stats.record_game(&GameResult::new(winner, reason));
+            }
+        }
+        stats
+    }
+
+    #[test]
+    fn elo() {
+        let (diff, error) = stats(6, 2, 2).elo().unwrap();
+        assert!((diff - 147.2).abs() < 0.1, "{diff}");
+        assert!((error.unwrap() - 268.7).abs() < 0.1, "{error:?}");
+
+        let (diff, error) = stats(2, 2, 6).elo().unwrap();
+        assert!((diff + 147.2).abs() < 0.1, "{diff}");
+        assert!((error.unwrap() - 268.7).abs() < 0.1, "{error:?}");
+
+        let (diff, error) = stats(30, 40, 30).elo().unwrap();
+        assert_eq!(0.0, diff);
+        assert!((error.unwrap() - 53.2).abs() < 0.1, "{error:?}");
+    }
+
+    #[test]
+    fn elo_unbounded() {
+        assert_eq!(None, MatchStatistics::new(1).elo());
+        assert_eq!(None, stats(3, 0, 0).elo());
+        assert_eq!(None, stats(0, 0, 3).elo());
+
+        // The interval of the score reaches 0% and 100% with two games.
+        assert_eq!(Some((0.0, None)), stats(1, 0, 1).elo());
+        let (diff, error) = stats(3, 0, 1).elo().unwrap();
+        assert!((diff - 190.8).abs() < 0.1, "{diff}");
+        assert_eq!(None, error);
+    }
+}